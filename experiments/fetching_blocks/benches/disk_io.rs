@@ -1,55 +1,153 @@
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use std::fs::{self, File};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use fetching_blocks::buf::{aligned_span, blocks_touched, round_up};
+use fetching_blocks::cache::{BlockCache, PolicyKind};
+use fetching_blocks::hugepage::huge_page_info;
+use fetching_blocks::load::{run_reads, Engine, Limit, LoadSpec, LoadStats};
+use fetching_blocks::mapping::{MapAdvice, MapOptions, Mapping};
+use fetching_blocks::nocache::{
+    dio_alignment, drop_caches, evict_range, open_nocache, open_nocache_write,
+};
+use fetching_blocks::readahead::ReadaheadMode;
+use fetching_blocks::residency::page_size;
+use fetching_blocks::workload::{OffsetGen, OffsetPattern};
+use fetching_blocks::{
+    block_geometry, format_size, residency, AlignedBuf, BackendKind, BlockGeometry, BufPool,
+    Histogram, IoDelta, IoSnapshot, Measurement, PooledBuf, Report,
+};
+use std::env;
+use std::fs::{self, File, OpenOptions};
 use std::io::Write;
+use std::os::unix::fs::FileExt;
 use std::os::unix::io::AsRawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-const BLOCK_SIZE: usize = 4096;
+/// Latency of each `subblock_reads` size, kept for the cold/warm report
+/// printed after `cached_subblock_reads`
+static COLD_LATENCY: Mutex<Vec<(usize, Histogram)>> = Mutex::new(Vec::new());
+
+/// Every measurement of this invocation, for the JSON and CSV result files
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
 
 /// Create a test file with the specified size filled with random-ish data
+///
+/// Written in 1MB chunks so multi-GB files do not need a multi-GB buffer.
+/// The written pages are kept out of (macOS) or evicted from (Linux) the page
+/// cache, so the file starts cold.
 fn create_test_file(path: &Path, size: usize) {
     let mut file = File::create(path).expect("Failed to create test file");
-    let data: Vec<u8> = (0..size).map(|i| (i % 256) as u8).collect();
-    file.write_all(&data).expect("Failed to write test data");
+    #[cfg(target_os = "macos")]
+    unsafe {
+        libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1);
+    }
+    let chunk: Vec<u8> = (0..size.min(1 << 20)).map(|i| (i % 256) as u8).collect();
+    let mut remaining = size;
+    while remaining > 0 {
+        let n = remaining.min(chunk.len());
+        file.write_all(&chunk[..n])
+            .expect("Failed to write test data");
+        remaining -= n;
+    }
     file.sync_all().expect("Failed to sync file");
+    evict_range(&file, 0, size as u64).expect("Failed to evict test file");
 }
 
-/// Open a file with F_NOCACHE to bypass OS cache (macOS only)
-fn open_nocache(path: &Path) -> std::io::Result<File> {
-    let file = File::open(path)?;
-    unsafe {
-        if libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) == -1 {
-            return Err(std::io::Error::last_os_error());
-        }
+/// Block geometry of the device holding `path`, printed so results can be read against it
+///
+/// The filesystem and device are also added to the result files' environment.
+fn detect_geometry(path: &Path) -> BlockGeometry {
+    let geometry = block_geometry(path).expect("Failed to detect block geometry");
+    with_report(|report| report.environment.add_path(path));
+    println!(
+        "{}: device {}, logical block {}, physical block {}, optimal I/O {}, fs block {}",
+        path.display(),
+        geometry.device.as_deref().unwrap_or("unknown"),
+        format_size(geometry.logical_block_size),
+        format_size(geometry.physical_block_size),
+        geometry
+            .optimal_io_size
+            .map_or("-".to_string(), format_size),
+        format_size(geometry.fs_block_size),
+    );
+    geometry
+}
+
+/// Large test file for random-offset reads, reused across runs
+///
+/// Defaults to a 4GB file in `/tmp/disk_io_bench_random`. Set
+/// `DISK_IO_BENCH_DIR` to put it on the device under test and
+/// `DISK_IO_FILE_MB` to change its size. The file is only rewritten when its
+/// size changes, since filling several GB dominates a short benchmark run.
+fn large_test_file() -> PathBuf {
+    let dir = env::var("DISK_IO_BENCH_DIR").unwrap_or_else(|_| "/tmp/disk_io_bench_random".into());
+    let size_mb: usize = env::var("DISK_IO_FILE_MB")
+        .ok()
+        .map(|mb| mb.parse().expect("DISK_IO_FILE_MB must be an integer"))
+        .unwrap_or(4096);
+    let size = size_mb << 20;
+
+    fs::create_dir_all(&dir).expect("Failed to create test directory");
+    let path = Path::new(&dir).join("large_test_file.dat");
+    if fs::metadata(&path).map(|m| m.len() as usize).ok() != Some(size) {
+        create_test_file(&path, size);
     }
-    Ok(file)
+    // Earlier groups or runs may have left parts of a reused file cached
+    let file = File::open(&path).expect("Failed to open test file");
+    evict_range(&file, 0, size as u64).expect("Failed to evict test file");
+    path
 }
 
-/// Read specified number of bytes from a file using F_NOCACHE
-fn read_nocache(path: &Path, size: usize) -> std::io::Result<Vec<u8>> {
+/// Read specified number of bytes at `offset` from a file, bypassing the OS cache
+///
+/// The request is widened to the aligned span covering it, using the pool's
+/// alignment, which must satisfy the direct I/O alignment of the file. On
+/// Linux a 1KB read against a 4096-byte logical block device transfers a full
+/// 4KB, and a 2KB read starting at 3KB transfers 8KB.
+///
+/// The latency of the whole call, open included, is recorded in `latency`.
+fn read_nocache<'a>(
+    path: &Path,
+    offset: u64,
+    size: usize,
+    pool: &'a BufPool,
+    latency: &mut Histogram,
+) -> std::io::Result<PooledBuf<'a>> {
+    let start = Instant::now();
     let file = open_nocache(path)?;
-    let mut buffer = vec![0u8; size];
+    let (offset, len) = aligned_span(offset, size, pool.align());
+    let mut buffer = pool.get(len);
 
     let bytes_read = unsafe {
         libc::pread(
             file.as_raw_fd(),
             buffer.as_mut_ptr() as *mut libc::c_void,
-            size,
-            0,
+            len,
+            offset as libc::off_t,
         )
     };
 
     if bytes_read < 0 {
         return Err(std::io::Error::last_os_error());
     }
+    // A short read fetched less than it claims to measure
+    if bytes_read as usize != len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("short read: {} of {} bytes at {}", bytes_read, len, offset),
+        ));
+    }
 
+    latency.record_duration(start.elapsed());
     Ok(buffer)
 }
 
 /// Read specified number of bytes from a file using normal cached I/O
-fn read_cached(path: &Path, size: usize) -> std::io::Result<Vec<u8>> {
+fn read_cached<'a>(path: &Path, size: usize, pool: &'a BufPool) -> std::io::Result<PooledBuf<'a>> {
     let file = File::open(path)?;
-    let mut buffer = vec![0u8; size];
+    let mut buffer = pool.get(size);
 
     let bytes_read = unsafe {
         libc::pread(
@@ -67,37 +165,202 @@ fn read_cached(path: &Path, size: usize) -> std::io::Result<Vec<u8>> {
     Ok(buffer)
 }
 
-/// Warm the OS page cache by reading the file
-fn warm_cache(path: &Path, size: usize) {
+/// Write `data` at `offset` and wait for it to reach the device
+///
+/// `sync_data` (`fdatasync`) after every write puts the cost of getting the
+/// data onto the media, including any read-modify-write, inside the
+/// measurement. Only the write and sync are recorded in `latency`.
+fn write_synced(
+    file: &File,
+    data: &[u8],
+    offset: u64,
+    latency: &mut Histogram,
+) -> std::io::Result<()> {
+    let start = Instant::now();
+    let written = unsafe {
+        libc::pwrite(
+            file.as_raw_fd(),
+            data.as_ptr() as *const libc::c_void,
+            data.len(),
+            offset as libc::off_t,
+        )
+    };
+    if written < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    file.sync_data()?;
+    latency.record_duration(start.elapsed());
+    Ok(())
+}
+
+/// Panic unless none of the pages covering `[offset, offset + len)` are cached
+///
+/// Called before each measured cold read, so a result never silently comes
+/// from the page cache.
+fn assert_cold(file: &File, offset: u64, len: usize) {
+    let state = residency(file, offset, len).expect("Failed to check page residency");
+    assert!(
+        state.is_cold(),
+        "{} of {} pages at offset {} are cached before a cold read",
+        state.resident,
+        state.pages,
+        offset
+    );
+}
+
+/// Evict `[offset, offset + len)` and wait until none of it is cached
+///
+/// The page cache holds file data in folios of up to 2MB, and a folio only
+/// partly inside the evicted range stays cached, so the range is widened to
+/// 2MB boundaries first. Readahead started by an earlier read may also still
+/// be in flight, and pages under I/O cannot be evicted, so eviction is
+/// retried for up to a second before giving up through `assert_cold`.
+fn make_cold(file: &File, offset: u64, len: usize) {
+    let (start, span) = aligned_span(offset, len, 2 << 20);
+    for _ in 0..1000 {
+        evict_range(file, start, span as u64).expect("Failed to evict test file");
+        let state = residency(file, offset, len).expect("Failed to check page residency");
+        if state.is_cold() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_cold(file, offset, len);
+}
+
+/// Panic unless every page covering `[offset, offset + len)` is cached
+fn assert_warm(file: &File, offset: u64, len: usize) {
+    let state = residency(file, offset, len).expect("Failed to check page residency");
+    assert!(
+        state.is_warm(),
+        "only {} of {} pages at offset {} are cached before a warm read",
+        state.resident,
+        state.pages,
+        offset
+    );
+}
+
+/// Print tail latency for one benchmark next to Criterion's summary
+///
+/// The histogram covers every iteration Criterion ran, warm-up included.
+/// Benchmarks skipped by a filter record nothing and print nothing.
+fn print_latency(label: &str, latency: &Histogram) {
+    if latency.is_empty() {
+        return;
+    }
+    println!("{:<40} {}", label, latency.percentiles().format_us());
+}
+
+/// Snapshot the kernel I/O counters for the device holding `path`
+fn io_snapshot(path: &Path) -> IoSnapshot {
+    IoSnapshot::take(path).expect("Failed to snapshot I/O counters")
+}
+
+/// Print what the device actually transferred for `reads` logical reads
+///
+/// With O_DIRECT every read reaches the block layer, so sectors per read show
+/// the real fetch size: a 2KB read on a 4KB logical block device reports 8
+/// sectors. Device counters include other processes' I/O.
+fn print_io(label: &str, delta: &IoDelta, reads: u64) {
+    if reads == 0 {
+        return;
+    }
+    println!("{:<40} {}", label, delta.describe(reads));
+}
+
+/// Add a measurement to this invocation's result files and rewrite them
+///
+/// The files go to `DISK_IO_RESULTS_DIR` (default `target/disk_io_results`)
+/// as `disk_io-<unix time>.json` and `.csv`, and are rewritten after every
+/// measurement so an interrupted run still leaves the results so far.
+/// Benchmarks skipped by a filter measure nothing and are left out.
+fn record(measurement: Measurement) {
+    if measurement.operations == 0 {
+        return;
+    }
+    with_report(|report| {
+        report.measurements.push(measurement);
+
+        let dir =
+            env::var("DISK_IO_RESULTS_DIR").unwrap_or_else(|_| "target/disk_io_results".into());
+        let (json, _) = report
+            .save(Path::new(&dir), &format!("disk_io-{}", report.created))
+            .expect("Failed to save results");
+        if report.measurements.len() == 1 {
+            println!("Saving results to {} and .csv", json.display());
+        }
+    });
+}
+
+/// Run `f` on this invocation's report, creating it on first use
+fn with_report<T>(f: impl FnOnce(&mut Report) -> T) -> T {
+    let mut report = REPORT.lock().unwrap();
+    f(report.get_or_insert_with(|| Report::new("disk_io")))
+}
+
+/// Warm the OS page cache by reading the file, then check that it worked
+fn warm_cache(path: &Path, size: usize, pool: &BufPool) {
     for _ in 0..10 {
-        let _ = read_cached(path, size);
+        let _ = read_cached(path, size, pool);
     }
+    assert_warm(
+        &File::open(path).expect("Failed to open test file"),
+        0,
+        size,
+    );
 }
 
 /// Benchmark comparing sub-block reads (2KB vs 4KB)
 ///
 /// This demonstrates that reading 2KB and 4KB from disk takes the same time
 /// because the disk reads in 4KB blocks - reading 2KB still fetches the full block.
+/// The sizes come from the detected block geometry, so on a device with
+/// larger physical blocks the sweep moves with them.
 fn benchmark_subblock_reads(c: &mut Criterion) {
     let test_dir = Path::new("/tmp/disk_io_bench_subblock");
     fs::create_dir_all(test_dir).expect("Failed to create test directory");
 
+    let sizes = detect_geometry(test_dir).read_sizes();
+
     // Create a test file large enough for our reads
     let path = test_dir.join("test_file.dat");
-    create_test_file(&path, BLOCK_SIZE); // 4KB file
+    create_test_file(&path, *sizes.last().unwrap());
 
-    let mut group = c.benchmark_group("subblock_reads");
+    // One pool for the whole group so the measured loop never allocates
+    let align = dio_alignment(&open_nocache(&path).expect("Failed to open test file"));
+    let pool = BufPool::new(align.max(std::mem::align_of::<usize>()));
 
-    // Test various read sizes within a single 4KB block
-    let sizes: [(usize, &str); 4] = [(1024, "1KB"), (2048, "2KB"), (3072, "3KB"), (4096, "4KB")];
+    let file = File::open(&path).expect("Failed to open test file");
+    let mut group = c.benchmark_group("subblock_reads");
 
-    for (size, name) in &sizes {
-        group.bench_with_input(BenchmarkId::from_parameter(*name), size, |b, &size| {
-            b.iter(|| {
-                let result = read_nocache(&path, size);
-                black_box(result.expect("Read failed"))
-            })
+    for size in &sizes {
+        let id = BenchmarkId::from_parameter(format_size(*size));
+        let label = format!("subblock_reads/{}", format_size(*size));
+        let mut latency = Histogram::new();
+        let before = io_snapshot(&path);
+        group.bench_with_input(id, size, |b, &size| {
+            b.iter_batched(
+                || assert_cold(&file, 0, size),
+                |()| {
+                    let result = read_nocache(&path, 0, size, &pool, &mut latency);
+                    black_box(result.expect("Read failed"))
+                },
+                BatchSize::PerIteration,
+            )
+        });
+        let io = before.delta(&io_snapshot(&path));
+        print_latency(&label, &latency);
+        print_io(&label, &io, latency.count());
+        record(Measurement {
+            group: "subblock_reads".into(),
+            name: label,
+            offset: Some(0),
+            backend: "pread".into(),
+            cache: "direct".into(),
+            io: Some(io),
+            ..Measurement::from_histogram(&latency, *size)
         });
+        COLD_LATENCY.lock().unwrap().push((*size, latency));
     }
 
     group.finish();
@@ -106,5 +369,1183 @@ fn benchmark_subblock_reads(c: &mut Criterion) {
     let _ = fs::remove_dir_all(test_dir);
 }
 
-criterion_group!(benches, benchmark_subblock_reads);
+/// Benchmark the `subblock_reads` size table against a warm page cache
+///
+/// The file is read until every page is cached (`warm_cache`), then each size
+/// is read from offset 0 with ordinary buffered `pread`s, so this is the cost
+/// of a page-cache hit: the baseline the cold numbers are paying to avoid.
+/// When `subblock_reads` ran in the same invocation, a table of cold/warm
+/// latency ratios per size follows the group.
+fn benchmark_cached_subblock_reads(c: &mut Criterion) {
+    let test_dir = Path::new("/tmp/disk_io_bench_cached");
+    fs::create_dir_all(test_dir).expect("Failed to create test directory");
+
+    let sizes = detect_geometry(test_dir).read_sizes();
+
+    let path = test_dir.join("test_file.dat");
+    create_test_file(&path, *sizes.last().unwrap());
+
+    let pool = BufPool::new(std::mem::align_of::<usize>());
+    let file = File::open(&path).expect("Failed to open test file");
+    let mut warm = Vec::new();
+
+    let mut group = c.benchmark_group("cached_subblock_reads");
+
+    for &size in &sizes {
+        let id = BenchmarkId::from_parameter(format_size(size));
+        let mut latency = Histogram::new();
+        group.bench_with_input(id, &size, |b, &size| {
+            warm_cache(&path, size, &pool);
+            b.iter_batched(
+                || assert_warm(&file, 0, size),
+                |()| {
+                    let start = Instant::now();
+                    let result = read_cached(&path, size, &pool).expect("Read failed");
+                    latency.record_duration(start.elapsed());
+                    black_box(result)
+                },
+                BatchSize::PerIteration,
+            )
+        });
+        let label = format!("cached_subblock_reads/{}", format_size(size));
+        print_latency(&label, &latency);
+        record(Measurement {
+            group: "cached_subblock_reads".into(),
+            name: label,
+            offset: Some(0),
+            backend: "pread".into(),
+            cache: "warm".into(),
+            ..Measurement::from_histogram(&latency, size)
+        });
+        warm.push((size, latency));
+    }
+
+    group.finish();
+
+    print_cold_warm_ratios(&COLD_LATENCY.lock().unwrap(), &warm);
+
+    let _ = fs::remove_dir_all(test_dir);
+}
+
+/// Print cold (direct) vs warm (cached) latency per read size
+///
+/// Sizes missing from either side, e.g. because a filter skipped one of the
+/// groups, are left out.
+fn print_cold_warm_ratios(cold: &[(usize, Histogram)], warm: &[(usize, Histogram)]) {
+    let rows: Vec<_> = warm
+        .iter()
+        .filter(|(_, warm)| !warm.is_empty())
+        .filter_map(|(size, warm)| {
+            let (_, cold) = cold
+                .iter()
+                .find(|(cold_size, cold)| cold_size == size && !cold.is_empty())?;
+            Some((*size, cold, warm))
+        })
+        .collect();
+    if rows.is_empty() {
+        return;
+    }
+
+    println!(
+        "{:>6} {:>12} {:>12} {:>12} {:>12} {:>10} {:>10}",
+        "size", "cold_mean_us", "warm_mean_us", "cold_p50_us", "warm_p50_us", "mean_x", "p50_x"
+    );
+    let us = |ns: f64| ns / 1e3;
+    for (size, cold, warm) in rows {
+        let (cold_p50, warm_p50) = (cold.value_at_quantile(0.5), warm.value_at_quantile(0.5));
+        println!(
+            "{:>6} {:>12.1} {:>12.1} {:>12.1} {:>12.1} {:>10.1} {:>10.1}",
+            format_size(size),
+            us(cold.mean()),
+            us(warm.mean()),
+            us(cold_p50 as f64),
+            us(warm_p50 as f64),
+            cold.mean() / warm.mean(),
+            cold_p50 as f64 / warm_p50.max(1) as f64,
+        );
+    }
+}
+
+/// Benchmark the same sub-block reads through each `IoBackend`
+///
+/// Every iteration opens the file, reads, and closes it again, like
+/// `read_nocache`, so the mmap backend pays for a fresh mapping and a cold
+/// page fault each time. Backends the platform lacks are skipped.
+fn benchmark_backend_reads(c: &mut Criterion) {
+    let test_dir = Path::new("/tmp/disk_io_bench_backends");
+    fs::create_dir_all(test_dir).expect("Failed to create test directory");
+
+    let sizes = detect_geometry(test_dir).read_sizes();
+
+    let path = test_dir.join("test_file.dat");
+    create_test_file(&path, *sizes.last().unwrap());
+
+    let align = dio_alignment(&open_nocache(&path).expect("Failed to open test file"));
+    let pool = BufPool::new(align.max(std::mem::align_of::<usize>()));
+
+    let mut group = c.benchmark_group("backend_reads");
+
+    for kind in BackendKind::ALL {
+        let mut backend = match kind.create() {
+            Ok(backend) => backend,
+            Err(e) => {
+                eprintln!("Skipping {} backend: {}", kind.name(), e);
+                continue;
+            }
+        };
+
+        for size in &sizes {
+            let id = BenchmarkId::new(kind.name(), format_size(*size));
+            let mut latency = Histogram::new();
+            group.bench_with_input(id, size, |b, &size| {
+                let mut buffer = pool.get(round_up(size, pool.align()));
+                b.iter(|| {
+                    let start = Instant::now();
+                    backend.open(&path, true).expect("Open failed");
+                    let n = backend.read_at(&mut buffer, 0).expect("Read failed");
+                    backend.close().expect("Close failed");
+                    latency.record_duration(start.elapsed());
+                    black_box(n)
+                })
+            });
+            record(Measurement {
+                group: "backend_reads".into(),
+                name: format!("backend_reads/{}/{}", kind.name(), format_size(*size)),
+                offset: Some(0),
+                backend: kind.name().into(),
+                cache: "direct".into(),
+                ..Measurement::from_histogram(&latency, *size)
+            });
+        }
+    }
+
+    group.finish();
+
+    let _ = fs::remove_dir_all(test_dir);
+}
+
+/// Benchmark sub-block reads at random block-aligned offsets in a large file
+///
+/// `subblock_reads` hits offset 0 every iteration, so after the first read the
+/// drive's own cache answers. Spreading reads uniformly (or with Zipfian skew)
+/// over a multi-GB file makes most reads go to the media, which is where the
+/// "sub-block reads cost a full block" claim has to hold.
+fn benchmark_random_reads(c: &mut Criterion) {
+    let path = large_test_file();
+    let file_size = fs::metadata(&path).expect("Failed to stat test file").len();
+    let geometry = detect_geometry(&path);
+    let sizes = geometry.read_sizes();
+    let block_size = geometry.fetch_unit() as u64;
+
+    let align = dio_alignment(&open_nocache(&path).expect("Failed to open test file"));
+    let pool = BufPool::new(align.max(std::mem::align_of::<usize>()));
+
+    let file = File::open(&path).expect("Failed to open test file");
+    let mut group = c.benchmark_group("random_subblock_reads");
+
+    let patterns = [
+        OffsetPattern::Uniform,
+        OffsetPattern::Zipfian { theta: 0.99 },
+    ];
+
+    // Every offset leaves room for the largest read before the end of the file
+    let span = file_size - *sizes.last().unwrap() as u64;
+    for pattern in patterns {
+        let mut offsets = OffsetGen::new(pattern, span, block_size, 42);
+        for size in &sizes {
+            let id = BenchmarkId::new(pattern.name(), format_size(*size));
+            let label = format!(
+                "random_subblock_reads/{}/{}",
+                pattern.name(),
+                format_size(*size)
+            );
+            let mut latency = Histogram::new();
+            let before = io_snapshot(&path);
+            group.bench_with_input(id, size, |b, &size| {
+                b.iter_batched(
+                    || {
+                        let offset = offsets.next_offset();
+                        assert_cold(&file, offset, size);
+                        offset
+                    },
+                    |offset| {
+                        let result = read_nocache(&path, offset, size, &pool, &mut latency);
+                        black_box(result.expect("Read failed"))
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            let io = before.delta(&io_snapshot(&path));
+            print_latency(&label, &latency);
+            print_io(&label, &io, latency.count());
+            record(Measurement {
+                group: "random_subblock_reads".into(),
+                name: label,
+                backend: "pread".into(),
+                cache: "direct".into(),
+                params: vec![("pattern".into(), pattern.name().into())],
+                io: Some(io),
+                ..Measurement::from_histogram(&latency, *size)
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Benchmark reads that start mid-block and straddle block boundaries
+///
+/// Sweeps the start offset within a block and the read length, so e.g. with
+/// 4KB blocks a 2KB read at offset 2048 fits in one block, the same read at
+/// 4095 touches two, and an 8KB read at 4095 touches three. Offsets and
+/// lengths scale with the detected fetch unit. Each read starts from a random block
+/// of the large test file to keep the drive's cache out of the picture. The
+/// block count is part of each benchmark ID and printed as a table before the
+/// group runs.
+fn benchmark_straddling_reads(c: &mut Criterion) {
+    let path = large_test_file();
+    let file_size = fs::metadata(&path).expect("Failed to stat test file").len();
+
+    let block_size = detect_geometry(&path).fetch_unit();
+
+    let align = dio_alignment(&open_nocache(&path).expect("Failed to open test file"));
+    let pool = BufPool::new(align.max(std::mem::align_of::<usize>()));
+
+    // 0, 512, 2048 and 4095 for 4KB blocks
+    let start_offsets = [0, block_size / 8, block_size / 2, block_size - 1].map(|o| o as u64);
+    // 512B up to 8KB for 4KB blocks
+    let lengths = [
+        block_size / 8,
+        block_size / 4,
+        block_size / 2,
+        block_size,
+        block_size * 2,
+    ];
+
+    println!("{:>8} {:>8} {:>14}", "offset", "length", "blocks_touched");
+    for start in start_offsets {
+        for len in lengths {
+            println!(
+                "{:>8} {:>8} {:>14}",
+                start,
+                format_size(len),
+                blocks_touched(start, len, block_size)
+            );
+        }
+    }
+
+    let file = File::open(&path).expect("Failed to open test file");
+    let mut group = c.benchmark_group("straddling_reads");
+
+    // Leave three spare blocks so the longest read never runs off the end
+    let range = file_size - 3 * block_size as u64;
+    let mut bases = OffsetGen::new(OffsetPattern::Uniform, range, block_size as u64, 7);
+
+    for start in start_offsets {
+        for len in lengths {
+            let blocks = blocks_touched(start, len, block_size);
+            let function = format!("offset_{}", start);
+            let parameter = format!("{}_{}blk", format_size(len), blocks);
+            let label = format!("straddling_reads/{}/{}", function, parameter);
+            let mut latency = Histogram::new();
+            let before = io_snapshot(&path);
+            group.bench_with_input(BenchmarkId::new(function, parameter), &len, |b, &len| {
+                b.iter_batched(
+                    || {
+                        let offset = bases.next_offset() + start;
+                        assert_cold(&file, offset, len);
+                        offset
+                    },
+                    |offset| {
+                        let result = read_nocache(&path, offset, len, &pool, &mut latency);
+                        black_box(result.expect("Read failed"))
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            let io = before.delta(&io_snapshot(&path));
+            print_latency(&label, &latency);
+            print_io(&label, &io, latency.count());
+            record(Measurement {
+                group: "straddling_reads".into(),
+                name: label,
+                offset: Some(start),
+                backend: "pread".into(),
+                cache: "direct".into(),
+                params: vec![("blocks_touched".into(), blocks.to_string())],
+                io: Some(io),
+                ..Measurement::from_histogram(&latency, len)
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Benchmark random sub-block reads with many reads in flight
+///
+/// For each engine (one thread per outstanding `pread`, or a single
+/// `io_uring` instance) and queue depth from 1 to 256, issues uniformly random
+/// reads of every size in the geometry's sweep. Criterion reports time per
+/// read (the inverse of IOPS) and bandwidth; a table of IOPS, bandwidth and
+/// latency per configuration follows the group, accumulated over every run
+/// Criterion made, warm-up included, along with device sectors per read.
+fn benchmark_queue_depth_reads(c: &mut Criterion) {
+    let path = large_test_file();
+    let geometry = detect_geometry(&path);
+    let sizes = geometry.read_sizes();
+    let depths = [1, 4, 16, 64, 256];
+    let engines = [Engine::Threads(BackendKind::Pread), Engine::IoUring];
+
+    let mut summary: Vec<(String, usize, usize, LoadStats, IoDelta)> = Vec::new();
+    let mut group = c.benchmark_group("queue_depth_reads");
+    group.sample_size(10);
+
+    for engine in engines {
+        for &depth in &depths {
+            for &size in &sizes {
+                let spec = LoadSpec {
+                    read_size: size,
+                    queue_depth: depth,
+                    pattern: OffsetPattern::Uniform,
+                    block_size: geometry.fetch_unit() as u64,
+                    direct: true,
+                    seed: 42,
+                };
+                if let Err(e) = run_reads(&path, &spec, engine, Limit::Reads(1)) {
+                    eprintln!("Skipping {} at depth {}: {}", engine.name(), depth, e);
+                    continue;
+                }
+
+                let mut total = LoadStats::default();
+                let before = io_snapshot(&path);
+                group.throughput(Throughput::Bytes(size as u64));
+                let id =
+                    BenchmarkId::new(format!("{}/qd{}", engine.name(), depth), format_size(size));
+                group.bench_with_input(id, &spec, |b, spec| {
+                    b.iter_custom(|iters| {
+                        let stats = run_reads(&path, spec, engine, Limit::Reads(iters))
+                            .expect("Load run failed");
+                        total.merge(&stats);
+                        stats.elapsed
+                    })
+                });
+                let io = before.delta(&io_snapshot(&path));
+                let backend = match engine {
+                    Engine::Threads(kind) => kind.name(),
+                    Engine::IoUring => "io_uring",
+                };
+                record(Measurement {
+                    group: "queue_depth_reads".into(),
+                    name: format!(
+                        "queue_depth_reads/{}/qd{}/{}",
+                        engine.name(),
+                        depth,
+                        format_size(size)
+                    ),
+                    backend: backend.into(),
+                    cache: "direct".into(),
+                    params: vec![
+                        ("engine".into(), engine.name()),
+                        ("pattern".into(), spec.pattern.name().into()),
+                    ],
+                    io: Some(io.clone()),
+                    ..Measurement::from_load(&total, size, depth)
+                });
+                summary.push((engine.name(), depth, size, total, io));
+            }
+        }
+    }
+
+    group.finish();
+
+    summary.retain(|(_, _, _, stats, _)| stats.reads > 0);
+    if summary.is_empty() {
+        return;
+    }
+    println!(
+        "{:<16} {:>5} {:>6} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "engine",
+        "qd",
+        "size",
+        "IOPS",
+        "MB/s",
+        "mean_us",
+        "p50_us",
+        "p99_us",
+        "p99.9_us",
+        "max_us",
+        "sect/read"
+    );
+    let us = |ns: u64| ns as f64 / 1e3;
+    for (engine, depth, size, stats, io) in &summary {
+        let p = stats.latency.percentiles();
+        println!(
+            "{:<16} {:>5} {:>6} {:>12.0} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10}",
+            engine,
+            depth,
+            format_size(*size),
+            stats.iops(),
+            stats.bandwidth() / 1e6,
+            stats.mean_latency().as_secs_f64() * 1e6,
+            us(p.p50),
+            us(p.p99),
+            us(p.p999),
+            us(p.max),
+            io.sectors_per_read(stats.reads)
+                .map_or("-".to_string(), |s| format!("{:.1}", s)),
+        );
+    }
+}
+
+/// Benchmark sub-block writes, each followed by `fdatasync`
+///
+/// The write-side companion to `subblock_reads`: the same size sweep written
+/// at random block-aligned offsets, two ways.
+/// - `direct`: O_DIRECT writes. A write smaller than the physical block (e.g.
+///   1KB on a 512e drive) forces the device to read, modify and rewrite the
+///   whole block. Sizes that are not a multiple of the direct I/O alignment
+///   are rejected by the kernel and skipped.
+/// - `buffered_cold`: page-cache writes to a page evicted just beforehand
+///   (outside the measurement). A partial-page write makes the kernel read
+///   the block before it can write it back; a full aligned page does not.
+fn benchmark_subblock_writes(c: &mut Criterion) {
+    let test_dir = Path::new("/tmp/disk_io_bench_writes");
+    fs::create_dir_all(test_dir).expect("Failed to create test directory");
+
+    let geometry = detect_geometry(test_dir);
+    let sizes = geometry.read_sizes();
+    let block_size = geometry.fetch_unit() as u64;
+
+    let path = test_dir.join("write_file.dat");
+    let file_size = 64 << 20;
+    create_test_file(&path, file_size);
+
+    let direct = open_nocache_write(&path).expect("Failed to open test file");
+    let align = dio_alignment(&direct);
+    let buffered = OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .expect("Failed to open test file");
+
+    let max_size = *sizes.last().unwrap();
+    let mut data = AlignedBuf::new(round_up(max_size, align), align.max(4096));
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+
+    let mut group = c.benchmark_group("subblock_writes");
+
+    for (mode, file) in [("direct", &direct), ("buffered_cold", &buffered)] {
+        let mut offsets = OffsetGen::new(
+            OffsetPattern::Uniform,
+            file_size as u64 - 2 * block_size,
+            block_size,
+            11,
+        );
+        for &size in &sizes {
+            if mode == "direct" && size % align != 0 {
+                eprintln!(
+                    "Skipping direct {} write: not a multiple of the {}-byte direct I/O alignment",
+                    format_size(size),
+                    align
+                );
+                continue;
+            }
+
+            let mut latency = Histogram::new();
+            let id = BenchmarkId::new(mode, format_size(size));
+            group.bench_with_input(id, &size, |b, &size| {
+                b.iter_batched(
+                    || {
+                        let offset = offsets.next_offset();
+                        if mode == "buffered_cold" {
+                            evict_range(file, offset, block_size)
+                                .expect("Failed to evict test file");
+                        }
+                        offset
+                    },
+                    |offset| {
+                        write_synced(file, &data[..size], offset, &mut latency)
+                            .expect("Write failed")
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            let label = format!("subblock_writes/{}/{}", mode, format_size(size));
+            print_latency(&label, &latency);
+            record(Measurement {
+                group: "subblock_writes".into(),
+                name: label,
+                backend: "pwrite".into(),
+                cache: if mode == "direct" { "direct" } else { "cold" }.into(),
+                ..Measurement::from_histogram(&latency, size)
+            });
+        }
+    }
+
+    group.finish();
+
+    let _ = fs::remove_dir_all(test_dir);
+}
+
+/// Benchmark buffered reads of a freshly evicted file against direct and cached reads
+///
+/// Three ways to read the same sizes at offset 0:
+/// - `buffered_cold`: an ordinary `pread` after the file's pages are evicted
+///   with `posix_fadvise(DONTNEED)`, or the whole page cache is dropped when
+///   `DISK_IO_DROP_CACHES=1` and running as root. This is what a storage
+///   engine without O_DIRECT sees on a cache miss, readahead included.
+/// - `nocache`: `read_nocache`, bypassing the cache entirely.
+/// - `cached`: `read_cached` after `warm_cache`, the page-cache hit baseline.
+///
+/// Eviction and the residency check run outside the measurement.
+fn benchmark_cold_cache_reads(c: &mut Criterion) {
+    let test_dir = Path::new("/tmp/disk_io_bench_cold");
+    fs::create_dir_all(test_dir).expect("Failed to create test directory");
+
+    let sizes = detect_geometry(test_dir).read_sizes();
+
+    let path = test_dir.join("test_file.dat");
+    let file_size = *sizes.last().unwrap();
+    create_test_file(&path, file_size);
+
+    let align = dio_alignment(&open_nocache(&path).expect("Failed to open test file"));
+    let pool = BufPool::new(align.max(std::mem::align_of::<usize>()));
+    let file = File::open(&path).expect("Failed to open test file");
+
+    let use_drop_caches = env::var("DISK_IO_DROP_CACHES").is_ok_and(|v| v == "1");
+    let evict = || {
+        if use_drop_caches {
+            drop_caches().expect("Failed to drop caches");
+        } else {
+            evict_range(&file, 0, file_size as u64).expect("Failed to evict test file");
+        }
+        assert_cold(&file, 0, file_size);
+    };
+
+    let mut group = c.benchmark_group("cold_cache_reads");
+
+    for mode in ["buffered_cold", "nocache", "cached"] {
+        for &size in &sizes {
+            let mut latency = Histogram::new();
+            let id = BenchmarkId::new(mode, format_size(size));
+            group.bench_with_input(id, &size, |b, &size| {
+                if mode == "cached" {
+                    warm_cache(&path, size, &pool);
+                }
+                b.iter_batched(
+                    || {
+                        if mode == "cached" {
+                            assert_warm(&file, 0, size);
+                        } else {
+                            evict();
+                        }
+                    },
+                    |()| {
+                        let result = if mode == "nocache" {
+                            read_nocache(&path, 0, size, &pool, &mut latency)
+                        } else {
+                            let start = Instant::now();
+                            let result = read_cached(&path, size, &pool);
+                            latency.record_duration(start.elapsed());
+                            result
+                        };
+                        black_box(result.expect("Read failed"))
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            let label = format!("cold_cache_reads/{}/{}", mode, format_size(size));
+            print_latency(&label, &latency);
+            let cache = match mode {
+                "buffered_cold" => "cold",
+                "nocache" => "direct",
+                _ => "warm",
+            };
+            record(Measurement {
+                group: "cold_cache_reads".into(),
+                name: label,
+                offset: Some(0),
+                backend: "pread".into(),
+                cache: cache.into(),
+                params: vec![("mode".into(), mode.into())],
+                ..Measurement::from_histogram(&latency, size)
+            });
+        }
+    }
+
+    group.finish();
+
+    let _ = fs::remove_dir_all(test_dir);
+}
+
+/// Benchmark buffered sequential reads under each readahead mode
+///
+/// Each iteration evicts an 8MB span of the large file, then reads it front
+/// to back in 1KB to 128KB chunks through a fresh descriptor configured with a
+/// `ReadaheadMode`. With readahead on, small chunks are served from pages the
+/// kernel already fetched and cost about as much per byte as large ones; with
+/// it disabled every page is a separate device read. Criterion reports
+/// bandwidth, and a table of bandwidth, device sectors per chunk read, read
+/// amplification and the average size of the requests the device saw follows
+/// the group. Modes the platform lacks are skipped.
+fn benchmark_readahead_reads(c: &mut Criterion) {
+    const SPAN: usize = 8 << 20;
+
+    let path = large_test_file();
+    let file_size = fs::metadata(&path).expect("Failed to stat test file").len();
+    detect_geometry(&path);
+
+    let chunks: Vec<usize> = (0..8).map(|shift| 1024 << shift).collect();
+    let file = File::open(&path).expect("Failed to open test file");
+    let mut buffer = vec![0u8; *chunks.last().unwrap()];
+    let mut bases = OffsetGen::new(
+        OffsetPattern::Sequential,
+        file_size - SPAN as u64,
+        SPAN as u64,
+        0,
+    );
+
+    let mut summary = Vec::new();
+    let mut group = c.benchmark_group("readahead_reads");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SPAN as u64));
+
+    for mode in ReadaheadMode::ALL {
+        if let Err(e) = mode.apply(&file, 0, 0) {
+            eprintln!("Skipping {} readahead: {}", mode.name(), e);
+            continue;
+        }
+        for &chunk in &chunks {
+            let (mut reads, mut elapsed) = (0u64, Duration::ZERO);
+            let before = io_snapshot(&path);
+            let id = BenchmarkId::new(mode.name(), format_size(chunk));
+            group.bench_with_input(id, &chunk, |b, &chunk| {
+                b.iter_batched(
+                    || {
+                        let base = bases.next_offset();
+                        make_cold(&file, base, SPAN);
+                        base
+                    },
+                    |base| {
+                        let start = Instant::now();
+                        let reader = File::open(&path).expect("Failed to open test file");
+                        mode.apply(&reader, base, SPAN as u64)
+                            .expect("Failed to set readahead mode");
+                        for pos in (0..SPAN).step_by(chunk) {
+                            let n = reader
+                                .read_at(&mut buffer[..chunk], base + pos as u64)
+                                .expect("Read failed");
+                            black_box(n);
+                        }
+                        elapsed += start.elapsed();
+                        reads += (SPAN / chunk) as u64;
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            if reads > 0 {
+                let io = before.delta(&io_snapshot(&path));
+                record(Measurement {
+                    group: "readahead_reads".into(),
+                    name: format!("readahead_reads/{}/{}", mode.name(), format_size(chunk)),
+                    size: chunk,
+                    backend: "pread".into(),
+                    cache: "cold".into(),
+                    queue_depth: 1,
+                    params: vec![("readahead".into(), mode.name().into())],
+                    operations: reads,
+                    bytes: reads * chunk as u64,
+                    elapsed,
+                    io: Some(io.clone()),
+                    ..Measurement::default()
+                });
+                summary.push((mode, chunk, reads, elapsed, io));
+            }
+        }
+    }
+
+    group.finish();
+
+    if summary.is_empty() {
+        return;
+    }
+    println!(
+        "{:<12} {:>6} {:>10} {:>10} {:>12} {:>10} {:>12}",
+        "readahead", "chunk", "MB/s", "sect/read", "device_MB", "read_amp", "KB/request"
+    );
+    let dash = || "-".to_string();
+    for (mode, chunk, reads, elapsed, io) in &summary {
+        let requested = reads * *chunk as u64;
+        let (device_mb, amplification, request_kb) = match io.disk {
+            Some(disk) => (
+                format!("{:.1}", disk.bytes_read() as f64 / 1e6),
+                format!("{:.2}x", disk.bytes_read() as f64 / requested as f64),
+                format!(
+                    "{:.1}",
+                    disk.bytes_read() as f64 / disk.reads_completed.max(1) as f64 / 1024.0
+                ),
+            ),
+            None => (dash(), dash(), dash()),
+        };
+        println!(
+            "{:<12} {:>6} {:>10.1} {:>10} {:>12} {:>10} {:>12}",
+            mode.name(),
+            format_size(*chunk),
+            requested as f64 / elapsed.as_secs_f64() / 1e6,
+            io.sectors_per_read(*reads)
+                .map_or_else(dash, |s| format!("{:.2}", s)),
+            device_mb,
+            amplification,
+            request_kb,
+        );
+    }
+}
+
+/// Benchmark reading through a fresh mapping against `read_nocache`
+///
+/// Each iteration maps just the pages covering a random page-aligned offset of
+/// the large file (evicted beforehand), copies 1 byte to 16 pages out of the
+/// mapping and unmaps it, so the time is dominated by the page faults. Modes:
+/// - `fault`: a plain mapping; the kernel reads around each faulting page
+/// - `random`: `MADV_RANDOM`, so each fault reads only its own page
+/// - `willneed`: `MADV_WILLNEED` on the mapping before touching it
+/// - `populate`: `MAP_POPULATE`, faulting everything in inside `mmap` itself
+/// - `pread_direct`: `read_nocache` of the same range, for comparison
+///
+/// A table of p50 latency per size and mode follows the group. Modes the
+/// platform lacks are skipped.
+fn benchmark_mmap_fault_reads(c: &mut Criterion) {
+    let path = large_test_file();
+    let file_size = fs::metadata(&path).expect("Failed to stat test file").len();
+    detect_geometry(&path);
+
+    let page = page_size();
+    let sizes = [1, page / 2, page, 4 * page, 16 * page];
+    let modes = [
+        ("fault", MapOptions::default()),
+        (
+            "random",
+            MapOptions {
+                advice: MapAdvice::Random,
+                ..MapOptions::default()
+            },
+        ),
+        (
+            "willneed",
+            MapOptions {
+                advice: MapAdvice::WillNeed,
+                ..MapOptions::default()
+            },
+        ),
+        (
+            "populate",
+            MapOptions {
+                populate: true,
+                ..MapOptions::default()
+            },
+        ),
+    ];
+
+    let file = File::open(&path).expect("Failed to open test file");
+    let align = dio_alignment(&open_nocache(&path).expect("Failed to open test file"));
+    let pool = BufPool::new(align.max(std::mem::align_of::<usize>()));
+    let mut buffer = vec![0u8; 16 * page];
+    let mut offsets = OffsetGen::new(
+        OffsetPattern::Uniform,
+        file_size - 16 * page as u64,
+        page as u64,
+        13,
+    );
+
+    let mut p50s: Vec<(&str, usize, u64)> = Vec::new();
+    let mut group = c.benchmark_group("mmap_fault_reads");
+
+    for (mode, options) in modes
+        .iter()
+        .map(|(m, o)| (*m, Some(o)))
+        .chain([("pread_direct", None)])
+    {
+        if let Some(options) = options {
+            let probe = File::open(&path).and_then(|f| Mapping::new(f, 0, page, options));
+            if let Err(e) = probe {
+                eprintln!("Skipping {} mapping: {}", mode, e);
+                continue;
+            }
+        }
+        for &size in &sizes {
+            let label = format!("mmap_fault_reads/{}/{}", mode, format_size(size));
+            let mut latency = Histogram::new();
+            let before = io_snapshot(&path);
+            let id = BenchmarkId::new(mode, format_size(size));
+            group.bench_with_input(id, &size, |b, &size| {
+                b.iter_batched(
+                    || {
+                        let offset = offsets.next_offset();
+                        make_cold(&file, offset, size);
+                        offset
+                    },
+                    |offset| match options {
+                        Some(options) => {
+                            let start = Instant::now();
+                            let mapped = File::open(&path)
+                                .and_then(|f| {
+                                    Mapping::new(f, offset, round_up(size, page), options)
+                                })
+                                .expect("Failed to map test file");
+                            buffer[..size].copy_from_slice(&mapped.as_slice()[..size]);
+                            drop(mapped);
+                            latency.record_duration(start.elapsed());
+                            black_box(buffer[size - 1]);
+                        }
+                        None => {
+                            let result = read_nocache(&path, offset, size, &pool, &mut latency);
+                            black_box(result.expect("Read failed"));
+                        }
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            let io = before.delta(&io_snapshot(&path));
+            print_latency(&label, &latency);
+            print_io(&label, &io, latency.count());
+            let backend = if options.is_some() { "mmap" } else { "pread" };
+            record(Measurement {
+                group: "mmap_fault_reads".into(),
+                name: label,
+                backend: backend.into(),
+                cache: if options.is_some() { "cold" } else { "direct" }.into(),
+                params: vec![("mode".into(), mode.into())],
+                io: Some(io),
+                ..Measurement::from_histogram(&latency, size)
+            });
+            if !latency.is_empty() {
+                p50s.push((mode, size, latency.value_at_quantile(0.5)));
+            }
+        }
+    }
+
+    group.finish();
+
+    if p50s.is_empty() {
+        return;
+    }
+    let mut columns: Vec<&str> = p50s.iter().map(|(mode, _, _)| *mode).collect();
+    columns.dedup();
+    print!("{:>6}", "p50_us");
+    for mode in &columns {
+        print!(" {:>12}", mode);
+    }
+    println!();
+    for &size in &sizes {
+        print!("{:>6}", format_size(size));
+        for mode in &columns {
+            match p50s.iter().find(|(m, s, _)| m == mode && *s == size) {
+                Some((_, _, p50)) => print!(" {:>12.1}", *p50 as f64 / 1e3),
+                None => print!(" {:>12}", "-"),
+            }
+        }
+        println!();
+    }
+}
+
+/// Benchmark 4KB to 2MB reads through mappings backed by 4KB pages and by huge pages
+///
+/// Each iteration maps one huge-page-sized, huge-page-aligned region of a
+/// file, copies the first `size` bytes out of it and unmaps it. Backings:
+/// - `pages`: the large file with `MADV_RANDOM`, faulting in 4KB at a time
+/// - `thp`: the large file with `MADV_HUGEPAGE`; where the filesystem supports
+///   large folios the page cache fetches and maps the whole 2MB on first touch
+/// - `hugetlbfs`: a file on a `hugetlbfs` mount, whose huge page is released
+///   with a hole punch before each iteration so the read faults a new one in
+///   (no disk I/O; this is the cost of allocating and zeroing a huge page)
+///
+/// File-backed regions are evicted before each iteration. A table of p50
+/// latency and device KB fetched per read follows the group: the point where
+/// a 4KB read costs as much as a 2MB one is where the fetch unit jumped.
+/// Backings the system lacks (THP disabled, no `hugetlbfs` mount or free huge
+/// pages) are skipped.
+fn benchmark_hugepage_reads(c: &mut Criterion) {
+    let info = huge_page_info();
+    let Some(huge) = info.pmd_size else {
+        eprintln!("Skipping hugepage_reads: no huge page support detected");
+        return;
+    };
+    println!(
+        "huge pages: {}, THP {}, hugetlbfs {} ({} free)",
+        format_size(huge),
+        info.thp_mode.as_deref().unwrap_or("unknown"),
+        info.hugetlbfs_mount
+            .as_ref()
+            .map_or("not mounted".to_string(), |dir| dir.display().to_string()),
+        info.free_huge_pages
+    );
+
+    let path = large_test_file();
+    detect_geometry(&path);
+    let file = File::open(&path).expect("Failed to open test file");
+
+    // 4KB, 16KB, ... up to the huge page size
+    let sizes: Vec<usize> = std::iter::successors(Some(page_size()), |s| Some(s * 4))
+        .take_while(|&s| s < huge)
+        .chain([huge])
+        .collect();
+
+    let mut backings = vec![(
+        "pages",
+        path.clone(),
+        MapOptions {
+            advice: MapAdvice::Random,
+            ..MapOptions::default()
+        },
+    )];
+    if info.thp_available() {
+        let options = MapOptions {
+            advice: MapAdvice::HugePage,
+            ..MapOptions::default()
+        };
+        backings.push(("thp", path.clone(), options));
+    } else {
+        eprintln!("Skipping thp backing: THP is disabled");
+    }
+    let hugetlbfs_file = match (&info.hugetlbfs_mount, info.hugetlbfs_available()) {
+        (Some(dir), true) => {
+            let hugetlbfs_path = dir.join("disk_io_bench_hugepage.dat");
+            let pages = info.free_huge_pages.min(16);
+            let created = File::create(&hugetlbfs_path)
+                .and_then(|f| f.set_len(pages * huge as u64).map(|_| f));
+            match created {
+                Ok(f) => {
+                    backings.push(("hugetlbfs", hugetlbfs_path.clone(), MapOptions::default()));
+                    Some((hugetlbfs_path, f))
+                }
+                Err(e) => {
+                    eprintln!("Skipping hugetlbfs backing: {}", e);
+                    None
+                }
+            }
+        }
+        _ => {
+            eprintln!("Skipping hugetlbfs backing: no mount with free huge pages");
+            None
+        }
+    };
+
+    let mut buffer = vec![0u8; huge];
+    let mut rows: Vec<(&str, usize, u64, Option<f64>)> = Vec::new();
+    let mut group = c.benchmark_group("hugepage_reads");
+
+    for (backing, backing_path, options) in &backings {
+        let backing_file = match &hugetlbfs_file {
+            Some((hugetlbfs_path, f)) if hugetlbfs_path == backing_path => f,
+            _ => &file,
+        };
+        let backing_size = backing_file.metadata().expect("Failed to stat file").len();
+        let mut bases = OffsetGen::new(
+            OffsetPattern::Uniform,
+            backing_size - huge as u64,
+            huge as u64,
+            17,
+        );
+        let on_disk = *backing != "hugetlbfs";
+
+        for &size in &sizes {
+            let label = format!("hugepage_reads/{}/{}", backing, format_size(size));
+            let mut latency = Histogram::new();
+            let before = io_snapshot(&path);
+            let id = BenchmarkId::new(*backing, format_size(size));
+            group.bench_with_input(id, &size, |b, &size| {
+                b.iter_batched(
+                    || {
+                        let base = bases.next_offset();
+                        if on_disk {
+                            make_cold(backing_file, base, huge);
+                        } else {
+                            punch_hole(backing_file, base, huge as u64);
+                        }
+                        base
+                    },
+                    |base| {
+                        let start = Instant::now();
+                        let mapped = File::open(backing_path)
+                            .and_then(|f| Mapping::new(f, base, huge, options))
+                            .expect("Failed to map file");
+                        buffer[..size].copy_from_slice(&mapped.as_slice()[..size]);
+                        drop(mapped);
+                        latency.record_duration(start.elapsed());
+                        black_box(buffer[size - 1]);
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            print_latency(&label, &latency);
+            if latency.is_empty() {
+                continue;
+            }
+            let io = on_disk.then(|| before.delta(&io_snapshot(&path)));
+            let fetched = io.as_ref().and_then(|io| {
+                print_io(&label, io, latency.count());
+                io.disk
+                    .map(|disk| disk.bytes_read() as f64 / latency.count() as f64 / 1024.0)
+            });
+            record(Measurement {
+                group: "hugepage_reads".into(),
+                name: label,
+                backend: "mmap".into(),
+                cache: "cold".into(),
+                params: vec![("backing".into(), backing.to_string())],
+                io,
+                ..Measurement::from_histogram(&latency, size)
+            });
+            rows.push((backing, size, latency.value_at_quantile(0.5), fetched));
+        }
+    }
+
+    group.finish();
+
+    if let Some((hugetlbfs_path, _)) = hugetlbfs_file {
+        let _ = fs::remove_file(hugetlbfs_path);
+    }
+
+    if rows.is_empty() {
+        return;
+    }
+    println!(
+        "{:<10} {:>6} {:>10} {:>12}",
+        "backing", "size", "p50_us", "KB/read"
+    );
+    for (backing, size, p50, fetched) in rows {
+        println!(
+            "{:<10} {:>6} {:>10.1} {:>12}",
+            backing,
+            format_size(size),
+            p50 as f64 / 1e3,
+            fetched.map_or("-".to_string(), |kb| format!("{:.1}", kb))
+        );
+    }
+}
+
+/// Benchmark block reads through a `BlockCache` with each eviction policy
+///
+/// Zipfian (theta 0.99) fetch-unit reads of the large file go through a cache
+/// of 16 shards holding a sixteenth of its blocks; misses are direct reads.
+/// Each policy starts with an empty cache, and the hit ratio, evictions and
+/// latency cover the whole run, warm-up included. A table comparing the
+/// policies follows.
+fn benchmark_block_cache_reads(c: &mut Criterion) {
+    let path = large_test_file();
+    let file_size = fs::metadata(&path).expect("Failed to stat test file").len();
+    let block_size = detect_geometry(&path).fetch_unit();
+    let capacity = (file_size / block_size as u64 / 16).max(1) as usize;
+
+    let mut group = c.benchmark_group("block_cache_reads");
+    let mut rows = Vec::new();
+
+    for policy in PolicyKind::ALL {
+        let cache = BlockCache::new(policy, capacity, block_size, 16);
+        let file = cache.open(&path).expect("Failed to open test file");
+        let mut offsets = OffsetGen::new(
+            OffsetPattern::Zipfian { theta: 0.99 },
+            file_size - block_size as u64,
+            block_size as u64,
+            42,
+        );
+        let mut buffer = vec![0u8; block_size];
+        let label = format!(
+            "block_cache_reads/{}/{}",
+            policy.name(),
+            format_size(block_size)
+        );
+        let mut latency = Histogram::new();
+        let before = io_snapshot(&path);
+        let id = BenchmarkId::new(policy.name(), format_size(block_size));
+        group.bench_function(id, |b| {
+            b.iter_batched(
+                || offsets.next_offset(),
+                |offset| {
+                    let start = Instant::now();
+                    let n = cache
+                        .read_at(&file, &mut buffer, offset)
+                        .expect("Read failed");
+                    latency.record_duration(start.elapsed());
+                    black_box(n)
+                },
+                BatchSize::PerIteration,
+            )
+        });
+        let io = before.delta(&io_snapshot(&path));
+        let stats = cache.stats();
+        print_latency(&label, &latency);
+        print_io(&label, &io, latency.count());
+        record(Measurement {
+            group: "block_cache_reads".into(),
+            name: label,
+            backend: "pread".into(),
+            cache: "direct".into(),
+            params: vec![
+                ("policy".into(), policy.name().into()),
+                ("capacity_blocks".into(), capacity.to_string()),
+                ("hit_ratio".into(), format!("{:.3}", stats.hit_ratio())),
+            ],
+            io: Some(io),
+            ..Measurement::from_histogram(&latency, block_size)
+        });
+        rows.push((policy, stats, latency));
+    }
+
+    group.finish();
+
+    println!(
+        "{:<7} {:>9} {:>9} {:>10} {:>10} {:>10}",
+        "policy", "capacity", "hit_ratio", "evictions", "p50_us", "p99_us"
+    );
+    for (policy, stats, latency) in rows {
+        println!(
+            "{:<7} {:>9} {:>9.3} {:>10} {:>10.1} {:>10.1}",
+            policy.name(),
+            capacity,
+            stats.hit_ratio(),
+            stats.evictions,
+            latency.value_at_quantile(0.5) as f64 / 1e3,
+            latency.value_at_quantile(0.99) as f64 / 1e3
+        );
+    }
+}
+
+/// Release `[offset, offset + len)` of a file's backing memory (Linux only)
+fn punch_hole(file: &File, offset: u64, len: u64) {
+    #[cfg(target_os = "linux")]
+    {
+        let rc = unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                offset as libc::off_t,
+                len as libc::off_t,
+            )
+        };
+        if rc != 0 {
+            panic!("Failed to punch hole: {}", std::io::Error::last_os_error());
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (file, offset, len);
+}
+
+criterion_group!(
+    benches,
+    benchmark_subblock_reads,
+    benchmark_cached_subblock_reads,
+    benchmark_backend_reads,
+    benchmark_random_reads,
+    benchmark_straddling_reads,
+    benchmark_queue_depth_reads,
+    benchmark_subblock_writes,
+    benchmark_cold_cache_reads,
+    benchmark_readahead_reads,
+    benchmark_mmap_fault_reads,
+    benchmark_hugepage_reads,
+    benchmark_block_cache_reads
+);
 criterion_main!(benches);
@@ -0,0 +1,87 @@
+//! Reads served by page faults on a shared file mapping.
+
+use std::fs::File;
+use std::io::{self, IoSliceMut};
+use std::path::Path;
+
+use super::{not_open, IoBackend};
+use crate::file_size;
+use crate::mapping::{MapAdvice, MapOptions, Mapping};
+use crate::nocache::evict_range;
+
+/// Maps the whole file and copies out of the mapping
+///
+/// Copying touches every page in the requested range, so a read costs one
+/// page fault per non-resident page. `mmap` always goes through the page
+/// cache; when opened with `direct` the backend instead evicts the file's
+/// cached pages (Linux only) and disables fault readahead with
+/// `MADV_RANDOM`, so each fault fetches only the page it needs. The eviction
+/// happens once, at `open`: pages a run has already faulted in stay cached,
+/// so over a long run most reads become page-cache hits.
+#[derive(Default)]
+pub struct MmapBackend {
+    map: Option<Mapping>,
+}
+
+impl MmapBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn mapped(&self) -> io::Result<&[u8]> {
+        Ok(self.map.as_ref().ok_or_else(not_open)?.as_slice())
+    }
+}
+
+impl IoBackend for MmapBackend {
+    fn name(&self) -> &'static str {
+        "mmap"
+    }
+
+    fn open(&mut self, path: &Path, direct: bool) -> io::Result<()> {
+        self.map = None;
+        let file = File::open(path)?;
+        // Metadata reports 0 bytes for block devices
+        let len = file_size(&file)? as usize;
+
+        if direct {
+            evict_range(&file, 0, 0)?;
+        }
+
+        let options = MapOptions {
+            advice: if direct {
+                MapAdvice::Random
+            } else {
+                MapAdvice::Normal
+            },
+            ..MapOptions::default()
+        };
+        self.map = Some(Mapping::new(file, 0, len, &options)?);
+        Ok(())
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mapped = self.mapped()?;
+        let start = (offset as usize).min(mapped.len());
+        let n = buf.len().min(mapped.len() - start);
+        buf[..n].copy_from_slice(&mapped[start..start + n]);
+        Ok(n)
+    }
+
+    fn read_vectored_at(&mut self, bufs: &mut [IoSliceMut<'_>], offset: u64) -> io::Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let n = self.read_at(buf, offset + total as u64)?;
+            total += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.map = None;
+        Ok(())
+    }
+}
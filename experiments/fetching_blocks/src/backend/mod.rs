@@ -0,0 +1,123 @@
+//! Interchangeable ways of reading a file.
+//!
+//! Each backend owns at most one open file at a time. The benchmarks drive
+//! them through `IoBackend` so the same loop can compare `pread`, `preadv`,
+//! page faults through `mmap`, and `io_uring` for the same read sizes.
+
+mod mmap;
+mod pread;
+#[cfg(target_os = "linux")]
+mod uring;
+
+pub use mmap::MmapBackend;
+pub use pread::{PreadBackend, PreadvBackend};
+#[cfg(target_os = "linux")]
+pub use uring::IoUringBackend;
+
+use std::fs::File;
+use std::io::{self, IoSliceMut};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::nocache::open_nocache;
+
+/// A way of issuing positioned reads against one open file
+pub trait IoBackend: Send {
+    /// Short name used in benchmark IDs and reports
+    fn name(&self) -> &'static str;
+
+    /// Open `path`, closing any previously opened file
+    ///
+    /// With `direct` set the file is opened so reads bypass the page cache,
+    /// in which case buffers, lengths and offsets must honour
+    /// `nocache::dio_alignment`.
+    fn open(&mut self, path: &Path, direct: bool) -> io::Result<()>;
+
+    /// Read into `buf` starting at `offset`, returning the bytes read
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Fill `bufs` in order from consecutive bytes starting at `offset`
+    fn read_vectored_at(&mut self, bufs: &mut [IoSliceMut<'_>], offset: u64) -> io::Result<usize>;
+
+    /// Close the open file, if any
+    fn close(&mut self) -> io::Result<()>;
+}
+
+/// Every backend this crate implements, for selecting one by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackendKind {
+    Pread,
+    Preadv,
+    Mmap,
+    IoUring,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 4] = [
+        BackendKind::Pread,
+        BackendKind::Preadv,
+        BackendKind::Mmap,
+        BackendKind::IoUring,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BackendKind::Pread => "pread",
+            BackendKind::Preadv => "preadv",
+            BackendKind::Mmap => "mmap",
+            BackendKind::IoUring => "io_uring",
+        }
+    }
+
+    /// Construct the backend, failing if the platform does not support it
+    pub fn create(self) -> io::Result<Box<dyn IoBackend>> {
+        Ok(match self {
+            BackendKind::Pread => Box::new(PreadBackend::new()),
+            BackendKind::Preadv => Box::new(PreadvBackend::new()),
+            BackendKind::Mmap => Box::new(MmapBackend::new()),
+            #[cfg(target_os = "linux")]
+            BackendKind::IoUring => Box::new(IoUringBackend::new(32)?),
+            #[cfg(not(target_os = "linux"))]
+            BackendKind::IoUring => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "io_uring is only available on Linux",
+                ))
+            }
+        })
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        BackendKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown backend '{}'", s))
+    }
+}
+
+/// Open `path` for reading, optionally bypassing the page cache
+fn open_file(path: &Path, direct: bool) -> io::Result<File> {
+    if direct {
+        open_nocache(path)
+    } else {
+        File::open(path)
+    }
+}
+
+/// Error returned when reading through a backend that has no open file
+fn not_open() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "backend has no open file")
+}
+
+/// Convert a raw syscall return value into a byte count
+fn check(ret: isize) -> io::Result<usize> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
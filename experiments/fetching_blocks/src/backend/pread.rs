@@ -0,0 +1,105 @@
+//! Plain `pread` and `preadv` system calls.
+
+use std::fs::File;
+use std::io::{self, IoSliceMut};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use super::{check, not_open, open_file, IoBackend};
+
+/// One `pread` per buffer
+#[derive(Default)]
+pub struct PreadBackend {
+    file: Option<File>,
+}
+
+impl PreadBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IoBackend for PreadBackend {
+    fn name(&self) -> &'static str {
+        "pread"
+    }
+
+    fn open(&mut self, path: &Path, direct: bool) -> io::Result<()> {
+        self.file = Some(open_file(path, direct)?);
+        Ok(())
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let file = self.file.as_ref().ok_or_else(not_open)?;
+        check(unsafe {
+            libc::pread(
+                file.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                offset as libc::off_t,
+            )
+        })
+    }
+
+    fn read_vectored_at(&mut self, bufs: &mut [IoSliceMut<'_>], offset: u64) -> io::Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let n = self.read_at(buf, offset + total as u64)?;
+            total += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.file = None;
+        Ok(())
+    }
+}
+
+/// A single `preadv` per call, scattering into every buffer at once
+#[derive(Default)]
+pub struct PreadvBackend {
+    file: Option<File>,
+}
+
+impl PreadvBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IoBackend for PreadvBackend {
+    fn name(&self) -> &'static str {
+        "preadv"
+    }
+
+    fn open(&mut self, path: &Path, direct: bool) -> io::Result<()> {
+        self.file = Some(open_file(path, direct)?);
+        Ok(())
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.read_vectored_at(&mut [IoSliceMut::new(buf)], offset)
+    }
+
+    fn read_vectored_at(&mut self, bufs: &mut [IoSliceMut<'_>], offset: u64) -> io::Result<usize> {
+        let file = self.file.as_ref().ok_or_else(not_open)?;
+        // IoSliceMut is guaranteed ABI compatible with iovec on Unix
+        check(unsafe {
+            libc::preadv(
+                file.as_raw_fd(),
+                bufs.as_ptr() as *const libc::iovec,
+                bufs.len() as libc::c_int,
+                offset as libc::off_t,
+            )
+        })
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.file = None;
+        Ok(())
+    }
+}
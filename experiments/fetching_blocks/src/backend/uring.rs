@@ -0,0 +1,453 @@
+//! `io_uring` driven directly through its system calls.
+//!
+//! Only the handful of kernel structures needed for `IORING_OP_READV` are
+//! declared here; layouts follow `include/uapi/linux/io_uring.h`.
+
+use std::fs::File;
+use std::io::{self, IoSliceMut};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::{open_file, IoBackend};
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+const IORING_ENTER_GETEVENTS: libc::c_uint = 1;
+const IORING_OP_READV: u8 = 1;
+const IORING_OP_READ: u8 = 22;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+/// Submission queue entry
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+/// Completion queue entry
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// One of the three regions shared with the kernel
+struct RingMap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl RingMap {
+    fn new(fd: &OwnedFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RingMap {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    fn u32_at(&self, offset: u32) -> *mut u32 {
+        unsafe { self.ptr.add(offset as usize) as *mut u32 }
+    }
+
+    fn atomic_at(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*(self.u32_at(offset) as *const AtomicU32) }
+    }
+}
+
+impl Drop for RingMap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// Reads submitted through an `io_uring` instance
+///
+/// `read_at` and `read_vectored_at` submit one `IORING_OP_READV` and wait for
+/// its completion, so they measure the ring's per-request overhead at queue
+/// depth 1. Deeper queues go through `queue_read`, `submit_and_wait` and
+/// `reap`; don't mix the two styles while reads are in flight. Dropping the
+/// backend waits for any reads still in flight.
+pub struct IoUringBackend {
+    sq: RingMap,
+    cq: RingMap,
+    sqes: RingMap,
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+    file: Option<File>,
+    /// Entries pushed to the submission ring but not yet passed to the kernel
+    queued: u32,
+    /// Entries pushed whose completion has not been reaped yet; the kernel
+    /// may write into their buffers until it is
+    in_flight: u32,
+    // Declared last so the ring mappings are torn down before the fd closes
+    ring: OwnedFd,
+}
+
+// The rings are only touched through `&mut self`.
+unsafe impl Send for IoUringBackend {}
+
+impl IoUringBackend {
+    /// Set up a ring with room for `entries` submissions in flight
+    pub fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries as libc::c_long,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ring = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+
+        Ok(IoUringBackend {
+            sq: RingMap::new(&ring, sq_len, IORING_OFF_SQ_RING)?,
+            cq: RingMap::new(&ring, cq_len, IORING_OFF_CQ_RING)?,
+            sqes: RingMap::new(&ring, sqes_len, IORING_OFF_SQES)?,
+            sq_off: params.sq_off,
+            cq_off: params.cq_off,
+            file: None,
+            queued: 0,
+            in_flight: 0,
+            ring,
+        })
+    }
+
+    /// Queue a submission, returning false if the submission ring is full
+    fn push(&mut self, sqe: Sqe) -> bool {
+        let head = self.sq.atomic_at(self.sq_off.head).load(Ordering::Acquire);
+        let tail = self.sq.atomic_at(self.sq_off.tail).load(Ordering::Relaxed);
+        let entries = unsafe { *self.sq.u32_at(self.sq_off.ring_entries) };
+        if tail.wrapping_sub(head) >= entries {
+            return false;
+        }
+        let index = tail & unsafe { *self.sq.u32_at(self.sq_off.ring_mask) };
+        unsafe {
+            ptr::write((self.sqes.ptr as *mut Sqe).add(index as usize), sqe);
+            *self.sq.u32_at(self.sq_off.array).add(index as usize) = index;
+        }
+        self.sq
+            .atomic_at(self.sq_off.tail)
+            .store(tail.wrapping_add(1), Ordering::Release);
+        self.queued += 1;
+        self.in_flight += 1;
+        true
+    }
+
+    /// Take back the entries the kernel has not been given yet
+    fn retract_queued(&mut self) {
+        let tail = self.sq.atomic_at(self.sq_off.tail);
+        tail.store(
+            tail.load(Ordering::Relaxed).wrapping_sub(self.queued),
+            Ordering::Release,
+        );
+        self.in_flight -= self.queued;
+        self.queued = 0;
+    }
+
+    /// Drop queued entries and wait for every submitted one to complete,
+    /// discarding the results
+    fn wait_idle(&mut self) -> io::Result<()> {
+        self.retract_queued();
+        while self.in_flight > 0 {
+            self.enter(0, 1)?;
+            while self.reap().is_some() {}
+        }
+        Ok(())
+    }
+
+    /// Queue a read of `len` bytes at `offset` into `buf` without submitting it
+    ///
+    /// The completion is reported by `reap` under `user_data`.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must point to `len` writable bytes that stay valid, and are not
+    /// otherwise accessed, until the completion for `user_data` is reaped.
+    pub unsafe fn queue_read(
+        &mut self,
+        buf: *mut u8,
+        len: usize,
+        offset: u64,
+        user_data: u64,
+    ) -> io::Result<()> {
+        let file = self.file.as_ref().ok_or_else(super::not_open)?;
+        let sqe = Sqe {
+            opcode: IORING_OP_READ,
+            fd: file.as_raw_fd(),
+            off: offset,
+            addr: buf as u64,
+            len: len as u32,
+            user_data,
+            ..Sqe::default()
+        };
+        if !self.push(sqe) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "submission queue full",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Submit every queued read and block until at least `wait` completions are ready
+    ///
+    /// Reads the kernel did not accept stay queued for the next call.
+    pub fn submit_and_wait(&mut self, wait: u32) -> io::Result<()> {
+        let submitted = self.enter(self.queued, wait)?;
+        self.queued -= submitted.min(self.queued);
+        Ok(())
+    }
+
+    /// Take one finished read as `(user_data, bytes read or error)`
+    pub fn reap(&mut self) -> Option<(u64, io::Result<usize>)> {
+        let cqe = self.pop()?;
+        let result = if cqe.res < 0 {
+            Err(io::Error::from_raw_os_error(-cqe.res))
+        } else {
+            Ok(cqe.res as usize)
+        };
+        Some((cqe.user_data, result))
+    }
+
+    /// Tell the kernel about `to_submit` new entries and wait for `wait`
+    /// completions, returning how many entries it took
+    fn enter(&mut self, to_submit: u32, wait: u32) -> io::Result<u32> {
+        loop {
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.ring.as_raw_fd() as libc::c_long,
+                    to_submit as libc::c_long,
+                    wait as libc::c_long,
+                    IORING_ENTER_GETEVENTS as libc::c_long,
+                    ptr::null::<libc::sigset_t>(),
+                    0 as libc::c_long,
+                )
+            };
+            if ret >= 0 {
+                return Ok(ret as u32);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    /// Take the oldest completion, if one is ready
+    fn pop(&mut self) -> Option<Cqe> {
+        let head = self.cq.atomic_at(self.cq_off.head).load(Ordering::Relaxed);
+        let tail = self.cq.atomic_at(self.cq_off.tail).load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let index = head & unsafe { *self.cq.u32_at(self.cq_off.ring_mask) };
+        let cqe = unsafe {
+            *(self.cq.ptr.add(self.cq_off.cqes as usize) as *const Cqe).add(index as usize)
+        };
+        self.cq
+            .atomic_at(self.cq_off.head)
+            .store(head.wrapping_add(1), Ordering::Release);
+        self.in_flight = self.in_flight.saturating_sub(1);
+        Some(cqe)
+    }
+
+    fn readv_sqe(
+        &self,
+        bufs: &mut [IoSliceMut<'_>],
+        offset: u64,
+        user_data: u64,
+    ) -> io::Result<Sqe> {
+        let file = self.file.as_ref().ok_or_else(super::not_open)?;
+        Ok(Sqe {
+            opcode: IORING_OP_READV,
+            fd: file.as_raw_fd(),
+            off: offset,
+            addr: bufs.as_mut_ptr() as u64,
+            len: bufs.len() as u32,
+            user_data,
+            ..Sqe::default()
+        })
+    }
+}
+
+impl IoBackend for IoUringBackend {
+    fn name(&self) -> &'static str {
+        "io_uring"
+    }
+
+    fn open(&mut self, path: &Path, direct: bool) -> io::Result<()> {
+        self.file = Some(open_file(path, direct)?);
+        Ok(())
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.read_vectored_at(&mut [IoSliceMut::new(buf)], offset)
+    }
+
+    fn read_vectored_at(&mut self, bufs: &mut [IoSliceMut<'_>], offset: u64) -> io::Result<usize> {
+        let sqe = self.readv_sqe(bufs, offset, 0)?;
+        if !self.push(sqe) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "submission queue full",
+            ));
+        }
+        // The kernel only takes entries in order, so if any are left queued
+        // ours is among them and was never seen
+        let submitted = self.submit_and_wait(1);
+        if submitted.is_err() || self.queued > 0 {
+            self.retract_queued();
+            submitted?;
+            return Err(io::Error::other("io_uring_enter did not accept the read"));
+        }
+        // From here the kernel owns `bufs` until the completion is reaped
+        loop {
+            if let Some((_, result)) = self.reap() {
+                return result;
+            }
+            if let Err(e) = self.enter(0, 1) {
+                abort_in_flight(e);
+            }
+        }
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.file = None;
+        Ok(())
+    }
+}
+
+impl Drop for IoUringBackend {
+    fn drop(&mut self) {
+        if let Err(e) = self.wait_idle() {
+            abort_in_flight(e);
+        }
+    }
+}
+
+/// Give up when reads the kernel is still writing into cannot be waited
+/// for; returning would let their buffers be freed and reused under it
+fn abort_in_flight(err: io::Error) -> ! {
+    eprintln!("io_uring: failed to wait for reads in flight: {}", err);
+    std::process::abort()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_reads_in_flight() {
+        let Ok(mut ring) = IoUringBackend::new(8) else {
+            // io_uring is disabled or filtered on this host
+            return;
+        };
+        let path =
+            std::env::temp_dir().join(format!("fetching_blocks_uring_{}", std::process::id()));
+        let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        ring.open(&path, false).unwrap();
+
+        let mut buf = vec![0; 4096];
+        assert_eq!(ring.read_at(&mut buf, 8192).unwrap(), 4096);
+        assert_eq!(buf, data[8192..12288]);
+        assert_eq!(ring.in_flight, 0);
+
+        let mut bufs = vec![vec![0u8; 4096]; 4];
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            unsafe { ring.queue_read(buf.as_mut_ptr(), 4096, i as u64 * 4096, i as u64) }.unwrap();
+        }
+        ring.submit_and_wait(0).unwrap();
+        unsafe { ring.queue_read(bufs[0].as_mut_ptr(), 4096, 0, 9) }.unwrap();
+        assert_eq!(ring.in_flight, 5);
+        // The unsubmitted read is dropped, the others are waited for
+        ring.wait_idle().unwrap();
+        assert_eq!((ring.in_flight, ring.queued), (0, 0));
+        for (i, buf) in bufs.iter().enumerate() {
+            assert_eq!(buf[..], data[i * 4096..(i + 1) * 4096]);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,329 @@
+//! Aligned I/O buffers.
+//!
+//! Direct I/O (`O_DIRECT` on Linux) rejects buffers whose address or length is
+//! not a multiple of the device's logical block size, and `Vec<u8>` only
+//! guarantees byte alignment. `AlignedBuf` owns a zeroed allocation with a
+//! caller-chosen alignment, and `BufPool` recycles them so benchmark loops do
+//! not pay for an allocation on every iteration.
+
+use std::alloc::{self, Layout};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::Mutex;
+
+/// Round `n` up to the next multiple of `align`
+pub fn round_up(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+/// Round `n` down to the previous multiple of `align`
+pub fn round_down(n: usize, align: usize) -> usize {
+    n / align * align
+}
+
+/// Smallest `align`-aligned byte range `(start, len)` covering `[offset, offset + len)`
+///
+/// This is what direct I/O actually transfers for an unaligned request.
+pub fn aligned_span(offset: u64, len: usize, align: usize) -> (u64, usize) {
+    let start = round_down(offset as usize, align);
+    if len == 0 {
+        return (start as u64, 0);
+    }
+    let end = round_up(offset as usize + len, align);
+    (start as u64, end - start)
+}
+
+/// Number of `block_size` blocks the byte range `[offset, offset + len)` touches
+pub fn blocks_touched(offset: u64, len: usize, block_size: usize) -> usize {
+    aligned_span(offset, len, block_size).1 / block_size
+}
+
+/// Heap buffer whose address is aligned to a power of two
+///
+/// The buffer has a fixed capacity and a length that can be adjusted within
+/// it; slicing (via `Deref`) only exposes the first `len` bytes. The whole
+/// allocation is zeroed up front, so every byte is always initialized.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+// The buffer uniquely owns its allocation, like a `Vec<u8>`.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    /// Allocate a zeroed buffer of `capacity` bytes aligned to `align`
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn new(capacity: usize, align: usize) -> Self {
+        let layout =
+            Layout::from_size_align(capacity.max(1), align).expect("Invalid buffer alignment");
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        AlignedBuf {
+            ptr,
+            len: capacity,
+            layout,
+        }
+    }
+
+    /// Number of bytes exposed through slicing
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Total number of bytes allocated
+    pub fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// Alignment of the buffer's address in bytes
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    /// Change the visible length, e.g. to the number of bytes a read returned
+    ///
+    /// Panics if `len` exceeds the capacity.
+    pub fn set_len(&mut self, len: usize) {
+        assert!(
+            len <= self.capacity(),
+            "length {} exceeds capacity {}",
+            len,
+            self.capacity()
+        );
+        self.len = len;
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// Pool of `AlignedBuf`s sharing one alignment
+///
+/// Buffers handed out by `get` return to the pool when the guard is dropped.
+pub struct BufPool {
+    align: usize,
+    free: Mutex<Vec<AlignedBuf>>,
+}
+
+impl BufPool {
+    /// Create an empty pool; `align` must be a power of two
+    pub fn new(align: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        BufPool {
+            align,
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    /// Borrow a buffer of exactly `len` visible bytes
+    ///
+    /// Reuses a free buffer with enough capacity if there is one, otherwise
+    /// allocates a new one with capacity rounded up to the pool's alignment.
+    /// Reused buffers keep whatever bytes the previous user left in them.
+    pub fn get(&self, len: usize) -> PooledBuf<'_> {
+        let reused = {
+            let mut free = self.free.lock().unwrap();
+            free.iter()
+                .position(|buf| buf.capacity() >= len)
+                .map(|i| free.swap_remove(i))
+        };
+        let mut buf =
+            reused.unwrap_or_else(|| AlignedBuf::new(round_up(len.max(1), self.align), self.align));
+        buf.set_len(len);
+        PooledBuf {
+            buf: Some(buf),
+            pool: self,
+        }
+    }
+
+    /// Number of idle buffers waiting to be reused
+    pub fn idle(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+/// Buffer on loan from a `BufPool`
+pub struct PooledBuf<'a> {
+    buf: Option<AlignedBuf>,
+    pool: &'a BufPool,
+}
+
+impl PooledBuf<'_> {
+    /// Detach the buffer from the pool so it is freed rather than recycled
+    pub fn into_inner(mut self) -> AlignedBuf {
+        self.buf.take().unwrap()
+    }
+}
+
+impl Deref for PooledBuf<'_> {
+    type Target = AlignedBuf;
+
+    fn deref(&self) -> &AlignedBuf {
+        self.buf.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBuf<'_> {
+    fn deref_mut(&mut self) -> &mut AlignedBuf {
+        self.buf.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBuf<'_> {
+    fn drop(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.pool.free.lock().unwrap().push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding() {
+        assert_eq!(round_up(0, 512), 0);
+        assert_eq!(round_up(1, 512), 512);
+        assert_eq!(round_up(512, 512), 512);
+        assert_eq!(round_up(513, 512), 1024);
+        assert_eq!(round_down(0, 4096), 0);
+        assert_eq!(round_down(4095, 4096), 0);
+        assert_eq!(round_down(4096, 4096), 4096);
+        assert_eq!(round_down(8191, 4096), 4096);
+        // Alignments need not be powers of two
+        assert_eq!(round_up(7, 3), 9);
+        assert_eq!(round_down(7, 3), 6);
+    }
+
+    #[test]
+    fn spans() {
+        // Already aligned
+        assert_eq!(aligned_span(4096, 4096, 4096), (4096, 4096));
+        // Inside one block
+        assert_eq!(aligned_span(100, 200, 512), (0, 512));
+        assert_eq!(aligned_span(4096 + 1024, 2048, 4096), (4096, 4096));
+        // Crossing a block boundary costs both blocks
+        assert_eq!(aligned_span(3072, 2048, 4096), (0, 8192));
+        assert_eq!(aligned_span(511, 2, 512), (0, 1024));
+        // Past 4GB
+        assert_eq!(aligned_span((1 << 32) + 10, 4096, 4096), (1 << 32, 8192));
+        // Nothing to read transfers nothing
+        assert_eq!(aligned_span(1000, 0, 512), (512, 0));
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(blocks_touched(0, 0, 4096), 0);
+        assert_eq!(blocks_touched(0, 1, 4096), 1);
+        assert_eq!(blocks_touched(0, 4096, 4096), 1);
+        assert_eq!(blocks_touched(1, 4096, 4096), 2);
+        assert_eq!(blocks_touched(3072, 2048, 4096), 2);
+        assert_eq!(blocks_touched(4095, 8194, 4096), 4);
+        assert_eq!(blocks_touched(0, 16384, 512), 32);
+    }
+
+    #[test]
+    fn aligned_buffers() {
+        for align in [1, 512, 4096, 1 << 16] {
+            let mut buf = AlignedBuf::new(1000, align);
+            assert_eq!(buf.as_ptr() as usize % align, 0);
+            assert_eq!(buf.align(), align);
+            assert_eq!(buf.len(), 1000);
+            assert!(buf.iter().all(|&b| b == 0));
+            buf[999] = 7;
+            buf.set_len(10);
+            assert_eq!(buf.len(), 10);
+            buf.set_len(1000);
+            assert_eq!(buf[999], 7);
+        }
+        let empty = AlignedBuf::new(0, 512);
+        assert!(empty.is_empty());
+        assert_eq!(empty.capacity(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds capacity")]
+    fn set_len_past_capacity() {
+        AlignedBuf::new(512, 512).set_len(513);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid buffer alignment")]
+    fn alignment_must_be_power_of_two() {
+        AlignedBuf::new(512, 3);
+    }
+
+    #[test]
+    fn pool_reuses_buffers() {
+        let pool = BufPool::new(4096);
+        let first = {
+            let mut buf = pool.get(1000);
+            assert_eq!(buf.len(), 1000);
+            assert_eq!(buf.capacity(), 4096);
+            assert_eq!(buf.as_ptr() as usize % 4096, 0);
+            buf[0] = 9;
+            buf.as_ptr()
+        };
+        assert_eq!(pool.idle(), 1);
+
+        // Fits in the returned buffer, which keeps its old contents
+        {
+            let buf = pool.get(4096);
+            assert_eq!(buf.as_ptr(), first);
+            assert_eq!(buf[0], 9);
+            assert_eq!(pool.idle(), 0);
+            // Nothing free, so a second buffer is allocated
+            let other = pool.get(10);
+            assert_ne!(other.as_ptr(), first);
+        }
+        assert_eq!(pool.idle(), 2);
+
+        // Too big for either free buffer
+        let big = pool.get(5000);
+        assert_eq!(big.capacity(), 8192);
+        assert_eq!(pool.idle(), 2);
+
+        // Detached buffers do not come back
+        let detached = big.into_inner();
+        assert_eq!(detached.len(), 5000);
+        assert_eq!(pool.idle(), 2);
+    }
+}
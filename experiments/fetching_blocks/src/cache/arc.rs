@@ -0,0 +1,208 @@
+//! Adaptive Replacement Cache eviction (Megiddo and Modha, FAST '03).
+
+use super::lru::LruList;
+use super::{Access, BlockKey, Policy};
+
+/// Balances recency against frequency, adapting to the workload
+///
+/// Resident blocks are split between `t1` (seen once recently) and `t2`
+/// (seen at least twice). Ghost lists `b1` and `b2` remember the keys
+/// recently evicted from each, without their data. A miss that hits a ghost
+/// shows that list was too small, and moves the target size `p` of `t1`
+/// toward it: scans and one-off reads stay in `t1` and cannot flush the
+/// frequently used blocks in `t2`, as they would under LRU.
+pub struct ArcPolicy {
+    capacity: usize,
+    /// Target length of `t1`
+    p: usize,
+    t1: LruList,
+    t2: LruList,
+    b1: LruList,
+    b2: LruList,
+}
+
+impl ArcPolicy {
+    /// Panics if `capacity` is 0
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Cache capacity must be positive");
+        ArcPolicy {
+            capacity,
+            p: 0,
+            t1: LruList::new(),
+            t2: LruList::new(),
+            b1: LruList::new(),
+            b2: LruList::new(),
+        }
+    }
+
+    /// Evict the LRU block of `t1` or `t2` into its ghost list, if the cache
+    /// is full
+    fn replace(&mut self, in_b2: bool) -> Option<BlockKey> {
+        if self.t1.len() + self.t2.len() < self.capacity {
+            return None;
+        }
+        let t1 = self.t1.len();
+        if t1 > 0 && (t1 > self.p || (in_b2 && t1 == self.p)) {
+            let key = self.t1.pop_back()?;
+            self.b1.push_front(key);
+            Some(key)
+        } else {
+            let key = self.t2.pop_back()?;
+            self.b2.push_front(key);
+            Some(key)
+        }
+    }
+}
+
+impl Policy for ArcPolicy {
+    fn name(&self) -> &'static str {
+        "arc"
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.t1.len() + self.t2.len()
+    }
+
+    fn access(&mut self, key: BlockKey) -> Access {
+        if self.t1.remove(key) || self.t2.contains(key) {
+            self.t2.remove(key);
+            self.t2.push_front(key);
+            return Access::Hit;
+        }
+
+        if self.b1.contains(key) {
+            // Recency would have hit: grow t1
+            let delta = (self.b2.len() / self.b1.len()).max(1);
+            self.p = (self.p + delta).min(self.capacity);
+            let evicted = self.replace(false);
+            self.b1.remove(key);
+            self.t2.push_front(key);
+            return Access::Miss { evicted };
+        }
+        if self.b2.contains(key) {
+            // Frequency would have hit: grow t2
+            let delta = (self.b1.len() / self.b2.len()).max(1);
+            self.p = self.p.saturating_sub(delta);
+            let evicted = self.replace(true);
+            self.b2.remove(key);
+            self.t2.push_front(key);
+            return Access::Miss { evicted };
+        }
+
+        // A key ARC has not seen recently. Keep t1 + b1 within the capacity
+        // and all four lists within twice it.
+        let l1 = self.t1.len() + self.b1.len();
+        let total = l1 + self.t2.len() + self.b2.len();
+        let evicted = if l1 == self.capacity {
+            if self.t1.len() < self.capacity {
+                self.b1.pop_back();
+                self.replace(false)
+            } else {
+                // t1 is the whole cache; drop its LRU block without a ghost
+                self.t1.pop_back()
+            }
+        } else if total >= self.capacity {
+            if total == 2 * self.capacity {
+                self.b2.pop_back();
+            }
+            self.replace(false)
+        } else {
+            None
+        };
+        self.t1.push_front(key);
+        Access::Miss { evicted }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(block: u64) -> BlockKey {
+        BlockKey { file: 0, block }
+    }
+
+    fn evicted(access: Access) -> Option<u64> {
+        match access {
+            Access::Miss { evicted } => evicted.map(|key| key.block),
+            Access::Hit => panic!("expected a miss"),
+        }
+    }
+
+    fn check_invariants(arc: &ArcPolicy) {
+        let c = arc.capacity;
+        assert!(arc.t1.len() + arc.t2.len() <= c);
+        assert!(arc.t1.len() + arc.b1.len() <= c);
+        assert!(arc.t1.len() + arc.t2.len() + arc.b1.len() + arc.b2.len() <= 2 * c);
+        assert!(arc.p <= c);
+    }
+
+    #[test]
+    fn ghost_hits_adapt_target() {
+        let mut arc = ArcPolicy::new(2);
+        arc.access(key(1));
+        assert_eq!(arc.access(key(1)), Access::Hit);
+        arc.access(key(2));
+        // t1 = [2] is over its target of 0, so 2 becomes a ghost in b1
+        assert_eq!(evicted(arc.access(key(3))), Some(2));
+        assert!(arc.b1.contains(key(2)));
+        assert_eq!(arc.p, 0);
+
+        // A b1 ghost hit grows t1's target; t1 = [3] is now within it, so
+        // t2 gives up 1 and 2 returns to t2
+        assert_eq!(evicted(arc.access(key(2))), Some(1));
+        assert_eq!(arc.p, 1);
+        assert!(arc.t2.contains(key(2)) && arc.b2.contains(key(1)));
+
+        // A b2 ghost hit shrinks it again, evicting from t1
+        assert_eq!(evicted(arc.access(key(1))), Some(3));
+        assert_eq!(arc.p, 0);
+        assert!(arc.t2.contains(key(1)) && arc.b1.contains(key(3)));
+        check_invariants(&arc);
+    }
+
+    #[test]
+    fn scan_does_not_flush_frequent_blocks() {
+        let mut arc = ArcPolicy::new(4);
+        for _ in 0..2 {
+            for block in 0..2 {
+                arc.access(key(block));
+            }
+        }
+        for block in 100..200 {
+            arc.access(key(block));
+        }
+        assert_eq!(arc.access(key(0)), Access::Hit);
+        assert_eq!(arc.access(key(1)), Access::Hit);
+    }
+
+    #[test]
+    fn invariants_hold_under_random_access() {
+        for capacity in [1, 2, 5, 16] {
+            let mut arc = ArcPolicy::new(capacity);
+            let mut state = 0x2545_f491_4f6c_dd1du64;
+            for _ in 0..10_000 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                // Mostly a hot set that fits, sometimes a cold key
+                let block = if state.is_multiple_of(4) {
+                    state % 1000
+                } else {
+                    state % (capacity as u64 + 2)
+                };
+                let resident = arc.len();
+                match arc.access(key(block)) {
+                    Access::Hit => assert_eq!(arc.len(), resident),
+                    Access::Miss { evicted: Some(_) } => assert_eq!(arc.len(), capacity),
+                    Access::Miss { evicted: None } => assert_eq!(arc.len(), resident + 1),
+                }
+                check_invariants(&arc);
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! CLOCK (second-chance) eviction.
+
+use std::collections::HashMap;
+
+use super::{Access, BlockKey, Policy};
+
+struct Slot {
+    key: BlockKey,
+    referenced: bool,
+}
+
+/// Approximates LRU with one reference bit per block instead of a list
+///
+/// Blocks sit in a ring. A hit only sets the block's bit, so it needs no
+/// reordering (and in a real cache, no write to shared state beyond a flag).
+/// To make room, the hand sweeps the ring clearing set bits and evicts the
+/// first block whose bit was already clear. New blocks start with a clear
+/// bit, so a block read once goes before one that was read again.
+pub struct ClockPolicy {
+    capacity: usize,
+    ring: Vec<Slot>,
+    slots: HashMap<BlockKey, usize>,
+    hand: usize,
+}
+
+impl ClockPolicy {
+    /// Panics if `capacity` is 0
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Cache capacity must be positive");
+        ClockPolicy {
+            capacity,
+            ring: Vec::with_capacity(capacity),
+            slots: HashMap::with_capacity(capacity),
+            hand: 0,
+        }
+    }
+}
+
+impl Policy for ClockPolicy {
+    fn name(&self) -> &'static str {
+        "clock"
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    fn access(&mut self, key: BlockKey) -> Access {
+        if let Some(&slot) = self.slots.get(&key) {
+            self.ring[slot].referenced = true;
+            return Access::Hit;
+        }
+        let slot = Slot {
+            key,
+            referenced: false,
+        };
+        if self.ring.len() < self.capacity {
+            self.slots.insert(key, self.ring.len());
+            self.ring.push(slot);
+            return Access::Miss { evicted: None };
+        }
+
+        while self.ring[self.hand].referenced {
+            self.ring[self.hand].referenced = false;
+            self.hand = (self.hand + 1) % self.capacity;
+        }
+        let victim = std::mem::replace(&mut self.ring[self.hand], slot).key;
+        self.slots.remove(&victim);
+        self.slots.insert(key, self.hand);
+        self.hand = (self.hand + 1) % self.capacity;
+        Access::Miss {
+            evicted: Some(victim),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(block: u64) -> BlockKey {
+        BlockKey { file: 0, block }
+    }
+
+    fn evicted(access: Access) -> Option<u64> {
+        match access {
+            Access::Miss { evicted } => evicted.map(|key| key.block),
+            Access::Hit => panic!("expected a miss"),
+        }
+    }
+
+    #[test]
+    fn referenced_block_gets_second_chance() {
+        let mut clock = ClockPolicy::new(3);
+        for block in 0..3 {
+            assert_eq!(evicted(clock.access(key(block))), None);
+        }
+        assert_eq!(clock.access(key(0)), Access::Hit);
+
+        // The hand clears 0's bit and passes it, evicting 1
+        assert_eq!(evicted(clock.access(key(3))), Some(1));
+        assert!(!clock.ring[0].referenced);
+        // 0 was not read again, so the next sweep takes it
+        assert_eq!(evicted(clock.access(key(4))), Some(2));
+        assert_eq!(evicted(clock.access(key(5))), Some(0));
+    }
+
+    #[test]
+    fn all_referenced_evicts_after_full_sweep() {
+        let mut clock = ClockPolicy::new(3);
+        for block in 0..3 {
+            clock.access(key(block));
+        }
+        for block in 0..3 {
+            assert_eq!(clock.access(key(block)), Access::Hit);
+        }
+        // Every bit is cleared once, and the hand is back where it started
+        assert_eq!(evicted(clock.access(key(3))), Some(0));
+        assert!(clock.ring.iter().all(|slot| !slot.referenced));
+        assert_eq!(clock.len(), 3);
+    }
+}
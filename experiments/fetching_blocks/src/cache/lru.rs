@@ -0,0 +1,211 @@
+//! Least-recently-used eviction.
+
+use std::collections::HashMap;
+
+use super::{Access, BlockKey, Policy};
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: BlockKey,
+    prev: usize,
+    next: usize,
+}
+
+/// Keys ordered from most to least recently used, with O(1) lookup, move
+/// and removal
+///
+/// A doubly linked list threaded through a `Vec`, indexed by a map from key
+/// to slot; freed slots are reused. ARC keeps four of these.
+pub(super) struct LruList {
+    nodes: Vec<Node>,
+    slots: HashMap<BlockKey, usize>,
+    free: Vec<usize>,
+    /// Most recently used
+    head: usize,
+    /// Least recently used
+    tail: usize,
+}
+
+impl LruList {
+    pub(super) fn new() -> Self {
+        LruList {
+            nodes: Vec::new(),
+            slots: HashMap::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub(super) fn contains(&self, key: BlockKey) -> bool {
+        self.slots.contains_key(&key)
+    }
+
+    /// Insert `key`, which must not be in the list, as most recently used
+    pub(super) fn push_front(&mut self, key: BlockKey) {
+        let node = Node {
+            key,
+            prev: NIL,
+            next: self.head,
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        match self.head {
+            NIL => self.tail = slot,
+            head => self.nodes[head].prev = slot,
+        }
+        self.head = slot;
+        let previous = self.slots.insert(key, slot);
+        debug_assert!(previous.is_none(), "key already in the list");
+    }
+
+    /// Remove `key`, returning whether it was in the list
+    pub(super) fn remove(&mut self, key: BlockKey) -> bool {
+        let Some(slot) = self.slots.remove(&key) else {
+            return false;
+        };
+        let Node { prev, next, .. } = self.nodes[slot];
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+        self.free.push(slot);
+        true
+    }
+
+    /// Remove and return the least recently used key
+    pub(super) fn pop_back(&mut self) -> Option<BlockKey> {
+        let key = self.nodes.get(self.tail)?.key;
+        self.remove(key);
+        Some(key)
+    }
+
+    /// Make `key`, which must be in the list, the most recently used
+    pub(super) fn move_to_front(&mut self, key: BlockKey) {
+        self.remove(key);
+        self.push_front(key);
+    }
+}
+
+/// Evicts the block that was used longest ago
+pub struct LruPolicy {
+    capacity: usize,
+    list: LruList,
+}
+
+impl LruPolicy {
+    /// Panics if `capacity` is 0
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Cache capacity must be positive");
+        LruPolicy {
+            capacity,
+            list: LruList::new(),
+        }
+    }
+}
+
+impl Policy for LruPolicy {
+    fn name(&self) -> &'static str {
+        "lru"
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    fn access(&mut self, key: BlockKey) -> Access {
+        if self.list.contains(key) {
+            self.list.move_to_front(key);
+            return Access::Hit;
+        }
+        let evicted = if self.list.len() == self.capacity {
+            self.list.pop_back()
+        } else {
+            None
+        };
+        self.list.push_front(key);
+        Access::Miss { evicted }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(block: u64) -> BlockKey {
+        BlockKey { file: 0, block }
+    }
+
+    #[test]
+    fn list_order() {
+        let mut list = LruList::new();
+        for block in 0..4 {
+            list.push_front(key(block));
+        }
+        list.move_to_front(key(0));
+        list.move_to_front(key(0));
+        assert!(list.remove(key(2)));
+        assert!(!list.remove(key(2)));
+        // Freed slots are reused
+        list.push_front(key(4));
+        assert_eq!(list.nodes.len(), 4);
+
+        let mut order = Vec::new();
+        while let Some(key) = list.pop_back() {
+            order.push(key.block);
+        }
+        assert_eq!(order, [1, 3, 0, 4]);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut lru = LruPolicy::new(3);
+        for block in 0..3 {
+            assert_eq!(lru.access(key(block)), Access::Miss { evicted: None });
+        }
+        assert_eq!(lru.access(key(0)), Access::Hit);
+        // 1 is now the least recently used, then 2, then 0
+        assert_eq!(
+            lru.access(key(3)),
+            Access::Miss {
+                evicted: Some(key(1))
+            }
+        );
+        assert_eq!(
+            lru.access(key(4)),
+            Access::Miss {
+                evicted: Some(key(2))
+            }
+        );
+        assert_eq!(lru.access(key(0)), Access::Hit);
+        assert_eq!(
+            lru.access(key(1)),
+            Access::Miss {
+                evicted: Some(key(3))
+            }
+        );
+        assert_eq!(lru.len(), 3);
+    }
+}
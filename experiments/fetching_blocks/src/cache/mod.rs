@@ -0,0 +1,491 @@
+//! A user-space block cache over direct I/O.
+//!
+//! The benchmarks show reads are block-granular: a 1KB read costs a whole
+//! block. An engine that bypasses the page cache therefore caches whole
+//! blocks itself. `BlockCache` does that: every read is split into blocks
+//! keyed by (file, block index), and a block that is not cached is fetched in
+//! full with a direct `pread`, the same path the benchmarks' `read_nocache`
+//! takes. Which block goes when the cache is full is up to an interchangeable
+//! `Policy` (LRU, CLOCK or ARC).
+//!
+//! The cache is split into shards, each with its own lock, map and policy, so
+//! threads reading different blocks rarely contend. Blocks are fetched
+//! without holding a lock; two threads missing on the same block both read
+//! it and the second keeps the first's copy.
+
+mod arc;
+mod clock;
+mod lru;
+pub mod sim;
+
+pub use arc::ArcPolicy;
+pub use clock::ClockPolicy;
+pub use lru::LruPolicy;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::buf::AlignedBuf;
+use crate::nocache::{dio_alignment, open_nocache};
+
+/// A cached block: block `block` (offset `block * block_size`) of `file`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockKey {
+    /// Id the cache gave the file; see `CachedFile::id`
+    pub file: u64,
+    pub block: u64,
+}
+
+/// Outcome of `Policy::access`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Hit,
+    /// The key was admitted, evicting `evicted` if the cache was full
+    Miss {
+        evicted: Option<BlockKey>,
+    },
+}
+
+/// Decides which blocks stay cached
+///
+/// A policy tracks keys only; the cache holds the data. Policies are not
+/// thread-safe themselves, each shard of a `BlockCache` owns one behind its
+/// lock.
+pub trait Policy: Send {
+    /// Short name used in benchmark IDs and reports
+    fn name(&self) -> &'static str;
+
+    /// Most keys resident at once
+    fn capacity(&self) -> usize;
+
+    /// Keys resident now
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record a read of `key`, admitting it on a miss
+    ///
+    /// A miss on a full cache evicts exactly one resident key.
+    fn access(&mut self, key: BlockKey) -> Access;
+}
+
+/// Every policy this crate implements, for selecting one by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolicyKind {
+    Lru,
+    Clock,
+    Arc,
+}
+
+impl PolicyKind {
+    pub const ALL: [PolicyKind; 3] = [PolicyKind::Lru, PolicyKind::Clock, PolicyKind::Arc];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PolicyKind::Lru => "lru",
+            PolicyKind::Clock => "clock",
+            PolicyKind::Arc => "arc",
+        }
+    }
+
+    /// A policy holding up to `capacity` keys; panics if `capacity` is 0
+    pub fn create(self, capacity: usize) -> Box<dyn Policy> {
+        match self {
+            PolicyKind::Lru => Box::new(LruPolicy::new(capacity)),
+            PolicyKind::Clock => Box::new(ClockPolicy::new(capacity)),
+            PolicyKind::Arc => Box::new(ArcPolicy::new(capacity)),
+        }
+    }
+}
+
+impl FromStr for PolicyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        PolicyKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown eviction policy '{}'", s))
+    }
+}
+
+/// Counters since the cache was created or `reset_stats` was called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    /// Blocks read from the file
+    pub misses: u64,
+    pub evictions: u64,
+    /// Blocks cached now
+    pub resident: usize,
+}
+
+impl CacheStats {
+    /// Fraction of block lookups served from the cache
+    pub fn hit_ratio(&self) -> f64 {
+        self.hits as f64 / (self.hits + self.misses).max(1) as f64
+    }
+}
+
+/// A file opened for reading through a `BlockCache`
+pub struct CachedFile {
+    id: u64,
+    file: File,
+    len: u64,
+    align: usize,
+}
+
+impl CachedFile {
+    /// Id used in this file's `BlockKey`s; the same for every `open` of the
+    /// same file
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Size in bytes when the file was opened
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+struct Shard {
+    blocks: HashMap<BlockKey, Arc<AlignedBuf>>,
+    policy: Box<dyn Policy>,
+}
+
+/// A sharded cache of fixed-size file blocks read with direct I/O
+pub struct BlockCache {
+    block_size: usize,
+    capacity: usize,
+    policy: PolicyKind,
+    shards: Vec<Mutex<Shard>>,
+    /// `(st_dev, st_ino)` of every opened file; the index is its id
+    files: Mutex<Vec<(u64, u64)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl BlockCache {
+    /// A cache of up to `capacity` blocks of `block_size` bytes, split into
+    /// `shards` shards that each evict with their own `policy`
+    ///
+    /// The capacity is divided evenly between the shards, so each shard's
+    /// policy only sees its share of the keys; there are never more shards
+    /// than blocks. Panics if `capacity` or `block_size` is 0.
+    pub fn new(policy: PolicyKind, capacity: usize, block_size: usize, shards: usize) -> Self {
+        assert!(capacity > 0, "Cache capacity must be positive");
+        assert!(block_size > 0, "Block size must be positive");
+        let count = shards.clamp(1, capacity);
+        let shards = (0..count)
+            .map(|i| {
+                // The first `capacity % count` shards take one block more
+                let share = capacity / count + usize::from(i < capacity % count);
+                Mutex::new(Shard {
+                    blocks: HashMap::with_capacity(share),
+                    policy: policy.create(share),
+                })
+            })
+            .collect();
+        BlockCache {
+            block_size,
+            capacity,
+            policy,
+            shards,
+            files: Mutex::new(Vec::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Capacity in blocks
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> PolicyKind {
+        self.policy
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Open `path` for reading through the cache, bypassing the page cache
+    ///
+    /// Fails if the block size is not a multiple of the file's direct I/O
+    /// alignment.
+    pub fn open(&self, path: &Path) -> io::Result<CachedFile> {
+        let file = open_nocache(path)?;
+        let align = dio_alignment(&file);
+        if !self.block_size.is_multiple_of(align) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "block size {} is not a multiple of the direct I/O alignment {}",
+                    self.block_size, align
+                ),
+            ));
+        }
+        let metadata = file.metadata()?;
+        let identity = (metadata.dev(), metadata.ino());
+        let mut files = self.files.lock().unwrap();
+        let id = match files.iter().position(|f| *f == identity) {
+            Some(id) => id,
+            None => {
+                files.push(identity);
+                files.len() - 1
+            }
+        };
+        Ok(CachedFile {
+            id: id as u64,
+            len: crate::file_size(&file)?,
+            file,
+            align,
+        })
+    }
+
+    /// Read into `buf` starting at `offset` through the cache, returning the
+    /// bytes read, which are fewer than `buf.len()` only at the end of the file
+    pub fn read_at(&self, file: &CachedFile, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let block_size = self.block_size as u64;
+        let mut done = 0;
+        while done < buf.len() {
+            let position = offset + done as u64;
+            if position >= file.len {
+                break;
+            }
+            let block = self.block(file, position / block_size)?;
+            let start = (position % block_size) as usize;
+            if start >= block.len() {
+                break;
+            }
+            let n = (block.len() - start).min(buf.len() - done);
+            buf[done..done + n].copy_from_slice(&block[start..start + n]);
+            done += n;
+        }
+        Ok(done)
+    }
+
+    /// Block `index` of `file`, from the cache or read from the file
+    ///
+    /// The last block of a file is shorter than the block size.
+    pub fn block(&self, file: &CachedFile, index: u64) -> io::Result<Arc<AlignedBuf>> {
+        let key = BlockKey {
+            file: file.id,
+            block: index,
+        };
+        let shard = &self.shards[self.shard_of(key)];
+        {
+            let mut shard = shard.lock().unwrap();
+            if let Some(block) = shard.blocks.get(&key).cloned() {
+                shard.policy.access(key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(block);
+            }
+        }
+
+        let block = Arc::new(self.fetch(file, index)?);
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let mut shard = shard.lock().unwrap();
+        match shard.policy.access(key) {
+            // Another thread fetched it meanwhile
+            Access::Hit => Ok(shard.blocks[&key].clone()),
+            Access::Miss { evicted } => {
+                if let Some(evicted) = evicted {
+                    shard.blocks.remove(&evicted);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                shard.blocks.insert(key, block.clone());
+                Ok(block)
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            resident: self
+                .shards
+                .iter()
+                .map(|shard| shard.lock().unwrap().blocks.len())
+                .sum(),
+        }
+    }
+
+    /// Zero the hit, miss and eviction counters, keeping the cached blocks
+    pub fn reset_stats(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+    }
+
+    fn shard_of(&self, key: BlockKey) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Read block `index` of `file` with direct I/O
+    fn fetch(&self, file: &CachedFile, index: u64) -> io::Result<AlignedBuf> {
+        let mut buf = AlignedBuf::new(self.block_size, file.align);
+        let n = unsafe {
+            libc::pread(
+                file.file.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                self.block_size,
+                (index * self.block_size as u64) as libc::off_t,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.set_len(n as usize);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const BLOCK: usize = 4096;
+
+    /// A file of `blocks` blocks of varying bytes, removed on drop
+    ///
+    /// Lives in `DISK_IO_BENCH_DIR` when that is set, as for the benches,
+    /// since the default temporary directory may not support direct I/O.
+    struct TestFile(PathBuf);
+
+    impl TestFile {
+        fn new(name: &str, blocks: usize) -> Self {
+            let dir = match std::env::var_os("DISK_IO_BENCH_DIR") {
+                Some(dir) => PathBuf::from(dir),
+                None => std::env::temp_dir(),
+            };
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("fetching_blocks_{}_{}", name, std::process::id()));
+            let data: Vec<u8> = (0..blocks * BLOCK).map(|i| (i % 251) as u8).collect();
+            std::fs::write(&path, data).unwrap();
+            TestFile(path)
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Open `path` in `cache`, or `None` if its filesystem rejects direct
+    /// I/O (EINVAL, e.g. tmpfs before Linux 6.6 or overlayfs)
+    fn open(cache: &BlockCache, path: &TestFile) -> Option<CachedFile> {
+        match cache.open(&path.0) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                eprintln!(
+                    "skipping: {} does not support direct I/O ({}); set DISK_IO_BENCH_DIR",
+                    path.0.display(),
+                    e
+                );
+                None
+            }
+            Err(e) => panic!("{}: {}", path.0.display(), e),
+        }
+    }
+
+    #[test]
+    fn counts_across_shards() {
+        let path = TestFile::new("cache_counts", 16);
+        for policy in PolicyKind::ALL {
+            let cache = BlockCache::new(policy, 8, BLOCK, 4);
+            assert_eq!(cache.shards(), 4);
+            let Some(file) = open(&cache, &path) else {
+                return;
+            };
+            assert_eq!(file.len(), 16 * BLOCK as u64);
+
+            for block in 0..16 {
+                cache.block(&file, block).unwrap();
+            }
+            let stats = cache.stats();
+            assert_eq!((stats.hits, stats.misses), (0, 16));
+            // Each shard holds 2 blocks; every block past that evicted one
+            assert!(stats.resident <= 8);
+            assert_eq!(stats.evictions, 16 - stats.resident as u64);
+
+            let resident: Vec<u64> = (0..16)
+                .filter(|&block| {
+                    let key = BlockKey {
+                        file: file.id(),
+                        block,
+                    };
+                    let shard = cache.shards[cache.shard_of(key)].lock().unwrap();
+                    shard.blocks.contains_key(&key)
+                })
+                .collect();
+            for &block in &resident {
+                cache.block(&file, block).unwrap();
+            }
+            let stats = cache.stats();
+            assert_eq!(stats.hits, resident.len() as u64);
+            assert_eq!(stats.misses, 16);
+
+            cache.reset_stats();
+            let stats = cache.stats();
+            assert_eq!((stats.hits, stats.misses, stats.evictions), (0, 0, 0));
+            assert_eq!(stats.resident, resident.len());
+        }
+    }
+
+    #[test]
+    fn reads_match_file() {
+        let path = TestFile::new("cache_reads", 4);
+        let expected = std::fs::read(&path.0).unwrap();
+        let cache = BlockCache::new(PolicyKind::Lru, 2, BLOCK, 2);
+        let Some(file) = open(&cache, &path) else {
+            return;
+        };
+
+        // Within a block, across a boundary, and past the end of the file
+        for (offset, len) in [(10, 100), (BLOCK - 10, 20), (3 * BLOCK + 4000, 500)] {
+            let mut buf = vec![0; len];
+            let n = cache.read_at(&file, &mut buf, offset as u64).unwrap();
+            let end = (offset + len).min(expected.len());
+            assert_eq!(n, end - offset);
+            assert_eq!(&buf[..n], &expected[offset..end]);
+        }
+        // The straddling read fetched blocks 0 and 1, the last read block 3
+        assert_eq!(cache.stats().misses, 3);
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Block size must be positive")]
+    fn rejects_zero_block_size() {
+        BlockCache::new(PolicyKind::Lru, 8, 0, 1);
+    }
+}
@@ -0,0 +1,283 @@
+//! Offline cache simulation from block access traces.
+//!
+//! A trace is a text file of reads, one per line: `<file> <offset> <length>`,
+//! separated by spaces, tabs or commas, with blank lines and `#` comments
+//! skipped. The file is any name that identifies it (a path, an SST number);
+//! only the last two fields must be numbers, so names may contain spaces.
+//! Lengths take the same suffixes as read sizes (`4k`, `1M`).
+//!
+//! `Simulation` replays each read once, splitting it into blocks and feeding
+//! every block to one policy instance per (policy, capacity) pair, so a
+//! single pass over the trace yields a miss-ratio curve for every policy
+//! without touching a disk.
+
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+use super::{Access, BlockKey, Policy, PolicyKind};
+use crate::parse_size;
+
+/// One read from a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRead {
+    pub file: String,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl TraceRead {
+    /// Parse one trace line; `None` for blank and comment lines
+    pub fn parse(line: &str) -> Result<Option<TraceRead>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let fields = split_last(line).and_then(|(rest, len)| {
+            let (file, offset) = split_last(rest)?;
+            Some((file, offset, len)).filter(|_| !file.is_empty())
+        });
+        let Some((file, offset, len)) = fields else {
+            return Err(format!("expected <file> <offset> <length>, got '{}'", line));
+        };
+        let offset: u64 = offset
+            .parse()
+            .map_err(|_| format!("invalid offset '{}'", offset))?;
+        let len = parse_size(len).ok_or_else(|| format!("invalid length '{}'", len))? as u64;
+        if offset.checked_add(len).is_none() {
+            return Err(format!("read of {} bytes at {} overflows", len, offset));
+        }
+        Ok(Some(TraceRead {
+            file: file.to_string(),
+            offset,
+            len,
+        }))
+    }
+}
+
+/// The text before the last separator run, and the field after it
+fn split_last(s: &str) -> Option<(&str, &str)> {
+    let separator = |c: char| c.is_whitespace() || c == ',';
+    let i = s.rfind(separator)?;
+    Some((s[..i].trim_end_matches(separator), &s[i + 1..]))
+}
+
+/// Hits and misses of one policy at one capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimResult {
+    pub policy: PolicyKind,
+    /// Capacity in blocks
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl SimResult {
+    pub fn miss_ratio(&self) -> f64 {
+        self.misses as f64 / (self.hits + self.misses).max(1) as f64
+    }
+}
+
+/// Every policy at every capacity, fed the same block accesses
+pub struct Simulation {
+    block_size: u64,
+    policies: Vec<Box<dyn Policy>>,
+    results: Vec<SimResult>,
+    /// Trace file name to the id used in `BlockKey`s
+    files: HashMap<String, u64>,
+    /// Every block seen, for the compulsory misses no capacity avoids
+    seen: HashSet<BlockKey>,
+    reads: u64,
+    accesses: u64,
+}
+
+impl Simulation {
+    /// Simulate caches of `block_size`-byte blocks for every combination of
+    /// `policies` and `capacities` (in blocks)
+    ///
+    /// Panics if `block_size` or a capacity is 0.
+    pub fn new(block_size: u64, policies: &[PolicyKind], capacities: &[usize]) -> Self {
+        assert!(block_size > 0, "Block size must be positive");
+        let mut instances = Vec::new();
+        let mut results = Vec::new();
+        for &policy in policies {
+            for &capacity in capacities {
+                instances.push(policy.create(capacity));
+                results.push(SimResult {
+                    policy,
+                    capacity,
+                    hits: 0,
+                    misses: 0,
+                });
+            }
+        }
+        Simulation {
+            block_size,
+            policies: instances,
+            results,
+            files: HashMap::new(),
+            seen: HashSet::new(),
+            reads: 0,
+            accesses: 0,
+        }
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Replay one read; a read of 0 bytes touches no blocks
+    ///
+    /// Panics if the read ends past `u64::MAX`, which `TraceRead::parse`
+    /// rejects.
+    pub fn read(&mut self, read: &TraceRead) {
+        self.reads += 1;
+        if read.len == 0 {
+            return;
+        }
+        let file = match self.files.get(&read.file) {
+            Some(&id) => id,
+            None => {
+                let id = self.files.len() as u64;
+                self.files.insert(read.file.clone(), id);
+                id
+            }
+        };
+        let first = read.offset / self.block_size;
+        let end = read.offset.checked_add(read.len).expect("Read overflows");
+        let last = (end - 1) / self.block_size;
+        for block in first..=last {
+            self.access(BlockKey { file, block });
+        }
+    }
+
+    fn access(&mut self, key: BlockKey) {
+        self.accesses += 1;
+        self.seen.insert(key);
+        for (policy, result) in self.policies.iter_mut().zip(&mut self.results) {
+            match policy.access(key) {
+                Access::Hit => result.hits += 1,
+                Access::Miss { .. } => result.misses += 1,
+            }
+        }
+    }
+
+    /// Replay every read in a trace, failing on the first malformed line
+    pub fn replay(&mut self, trace: impl BufRead) -> Result<(), String> {
+        for (i, line) in trace.lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let read = TraceRead::parse(&line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            if let Some(read) = read {
+                self.read(&read);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads replayed
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Block accesses the reads split into
+    pub fn accesses(&self) -> u64 {
+        self.accesses
+    }
+
+    /// Distinct blocks accessed: the misses even an unbounded cache takes
+    pub fn unique_blocks(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Results in policy order, then capacity order as given to `new`; the
+    /// results of one policy are its miss-ratio curve
+    pub fn results(&self) -> &[SimResult] {
+        &self.results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "\
+# file offset length
+a 0 4k
+a 4096 4096
+
+a,0,8k
+b\t0 100
+b 50 0
+a 4000 200
+";
+
+    #[test]
+    fn parse() {
+        let read = |file: &str, offset, len| {
+            Some(TraceRead {
+                file: file.to_string(),
+                offset,
+                len,
+            })
+        };
+        assert_eq!(TraceRead::parse("a 0 4k"), Ok(read("a", 0, 4096)));
+        assert_eq!(TraceRead::parse(" a,, 12\t1M "), Ok(read("a", 12, 1 << 20)));
+        assert_eq!(TraceRead::parse("my sst 7 1"), Ok(read("my sst", 7, 1)));
+        assert_eq!(TraceRead::parse("# a 0 1"), Ok(None));
+        assert_eq!(TraceRead::parse("   "), Ok(None));
+
+        for line in [
+            "a 0",
+            "0 4k",
+            "a x 4k",
+            "a 0 -1",
+            "a 18446744073709551615 2",
+        ] {
+            assert!(TraceRead::parse(line).is_err(), "{}", line);
+        }
+        let max = format!("a {} 1", u64::MAX - 1);
+        assert_eq!(TraceRead::parse(&max), Ok(read("a", u64::MAX - 1, 1)));
+    }
+
+    #[test]
+    fn replay_counts() {
+        let mut sim = Simulation::new(4096, &PolicyKind::ALL, &[1, 2, 3]);
+        sim.replay(TRACE.as_bytes()).unwrap();
+        // Blocks a0 a1 | a0 a1 | b0 | a0 a1; the empty read touches none
+        assert_eq!(sim.reads(), 6);
+        assert_eq!(sim.accesses(), 7);
+        assert_eq!(sim.unique_blocks(), 3);
+
+        let counts: Vec<_> = sim
+            .results()
+            .iter()
+            .map(|r| (r.policy.name(), r.capacity, r.hits, r.misses))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                // No two consecutive accesses repeat a block
+                ("lru", 1, 0, 7),
+                // b0 evicts a0, which evicts a1, which evicts b0
+                ("lru", 2, 2, 5),
+                // Only the compulsory misses
+                ("lru", 3, 4, 3),
+                ("clock", 1, 0, 7),
+                ("clock", 2, 2, 5),
+                ("clock", 3, 4, 3),
+                ("arc", 1, 0, 7),
+                // a0 and a1 were read twice and sit in t2; b0 evicts a0 but
+                // a1 survives
+                ("arc", 2, 3, 4),
+                ("arc", 3, 4, 3),
+            ]
+        );
+        assert_eq!(sim.results()[1].miss_ratio(), 5.0 / 7.0);
+    }
+
+    #[test]
+    fn replay_reports_bad_line() {
+        let mut sim = Simulation::new(4096, &[PolicyKind::Lru], &[1]);
+        let err = sim.replay("a 0 1\na 1\n".as_bytes()).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+}
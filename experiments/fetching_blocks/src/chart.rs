@@ -0,0 +1,692 @@
+//! SVG line and bar charts of measurements.
+//!
+//! Read size goes on the x axis, one evenly spaced category per distinct size,
+//! and a metric (latency, IOPS or bandwidth) on the y axis, with one series
+//! per value of a field such as the backend or cache mode. The SVG has no
+//! scripts, fonts or stylesheets to fetch, so it can go straight into a post;
+//! `html_page` puts several charts on one page.
+
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use crate::format_size;
+use crate::report::Measurement;
+
+/// Okabe-Ito colors, which stay distinct under the common color blindnesses
+const PALETTE: [&str; 8] = [
+    "#0072b2", "#e69f00", "#009e73", "#d55e00", "#cc79a7", "#56b4e9", "#f0e442", "#000000",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+    /// One line per series, with a marker at each size
+    Line,
+    /// One bar per series, grouped by size
+    Bar,
+}
+
+impl ChartKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChartKind::Line => "line",
+            ChartKind::Bar => "bar",
+        }
+    }
+}
+
+impl FromStr for ChartKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(ChartKind::Line),
+            "bar" => Ok(ChartKind::Bar),
+            _ => Err(format!("unknown chart kind '{}'", s)),
+        }
+    }
+}
+
+/// What the y axis shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Mean,
+    P50,
+    P99,
+    Iops,
+    Bandwidth,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::Mean,
+        Metric::P50,
+        Metric::P99,
+        Metric::Iops,
+        Metric::Bandwidth,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Mean => "mean",
+            Metric::P50 => "p50",
+            Metric::P99 => "p99",
+            Metric::Iops => "iops",
+            Metric::Bandwidth => "bandwidth",
+        }
+    }
+
+    /// Axis label, with the unit of `value`
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Mean => "mean latency (µs)",
+            Metric::P50 => "p50 latency (µs)",
+            Metric::P99 => "p99 latency (µs)",
+            Metric::Iops => "IOPS",
+            Metric::Bandwidth => "bandwidth (MB/s)",
+        }
+    }
+
+    /// The metric for one measurement; `None` if it was not recorded
+    pub fn value(&self, m: &Measurement) -> Option<f64> {
+        let us = |ns: f64| ns / 1e3;
+        let value = match self {
+            Metric::Mean => us(m.latency?.mean),
+            Metric::P50 => us(m.latency?.p50 as f64),
+            Metric::P99 => us(m.latency?.p99 as f64),
+            Metric::Iops => m.iops(),
+            Metric::Bandwidth => m.bandwidth() / 1e6,
+        };
+        Some(value).filter(|v| v.is_finite())
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Metric::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| format!("unknown metric '{}'", s))
+    }
+}
+
+/// A measurement field to split series by or filter on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Group,
+    Backend,
+    Cache,
+    QueueDepth,
+    /// A group-specific parameter, e.g. `pattern`
+    Param(String),
+}
+
+impl Field {
+    /// The field's value in `m`; `None` for a parameter it does not have
+    pub fn value(&self, m: &Measurement) -> Option<String> {
+        match self {
+            Field::Group => Some(m.group.clone()),
+            Field::Backend => Some(m.backend.clone()),
+            Field::Cache => Some(m.cache.clone()),
+            Field::QueueDepth => Some(m.queue_depth.to_string()),
+            Field::Param(name) => m
+                .params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone()),
+        }
+    }
+}
+
+/// `group`, `backend`, `cache`, `qd` or `param:<name>`
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Group => f.write_str("group"),
+            Field::Backend => f.write_str("backend"),
+            Field::Cache => f.write_str("cache"),
+            Field::QueueDepth => f.write_str("qd"),
+            Field::Param(name) => write!(f, "param:{}", name),
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "group" => Ok(Field::Group),
+            "backend" => Ok(Field::Backend),
+            "cache" => Ok(Field::Cache),
+            "qd" => Ok(Field::QueueDepth),
+            _ => match s.strip_prefix("param:") {
+                Some(name) if !name.is_empty() => Ok(Field::Param(name.to_string())),
+                _ => Err(format!("unknown field '{}'", s)),
+            },
+        }
+    }
+}
+
+/// A chart of one metric against read size
+#[derive(Debug, Clone)]
+pub struct Chart {
+    pub title: String,
+    pub kind: ChartKind,
+    pub metric: Metric,
+    /// One series per distinct value of this field
+    pub series: Field,
+    /// Keep only measurements whose field has one of the listed values
+    pub filters: Vec<(Field, Vec<String>)>,
+    /// Image size in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The points to draw: a value per series and size, where measured
+struct Data {
+    sizes: Vec<usize>,
+    series: Vec<(String, Vec<Option<f64>>)>,
+}
+
+/// Plot area and y scale, in pixels
+struct Frame {
+    left: f64,
+    right: f64,
+    top: f64,
+    bottom: f64,
+    /// Value at the top of the y axis
+    y_max: f64,
+    y_step: f64,
+    /// Width of one size's slot on the x axis
+    band: f64,
+}
+
+impl Frame {
+    fn x(&self, index: usize) -> f64 {
+        self.left + (index as f64 + 0.5) * self.band
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        self.bottom - value / self.y_max * (self.bottom - self.top)
+    }
+}
+
+impl Chart {
+    /// A line chart of mean latency with one series per backend
+    pub fn new(title: &str) -> Self {
+        Chart {
+            title: title.to_string(),
+            kind: ChartKind::Line,
+            metric: Metric::Mean,
+            series: Field::Backend,
+            filters: Vec::new(),
+            width: 720,
+            height: 440,
+        }
+    }
+
+    /// The chart as a standalone SVG document
+    ///
+    /// Measurements the filters reject, or without the metric or the series
+    /// field, are left out. Fails if nothing is left or if two measurements
+    /// fall on the same point, which means a filter is missing.
+    pub fn render(&self, measurements: &[Measurement]) -> Result<String, String> {
+        let data = self.data(measurements)?;
+        let mut svg = String::new();
+        self.write_svg(&mut svg, &data)
+            .expect("writing to a String cannot fail");
+        Ok(svg)
+    }
+
+    fn data(&self, measurements: &[Measurement]) -> Result<Data, String> {
+        // (series, size, value, measurement name)
+        let mut points: Vec<(String, usize, f64, &str)> = Vec::new();
+        for m in measurements {
+            let keep = self
+                .filters
+                .iter()
+                .all(|(field, values)| field.value(m).is_some_and(|v| values.contains(&v)));
+            if !keep {
+                continue;
+            }
+            let (Some(series), Some(value)) = (self.series.value(m), self.metric.value(m)) else {
+                continue;
+            };
+            if let Some(other) = points
+                .iter()
+                .find(|(s, size, _, _)| *s == series && *size == m.size)
+            {
+                return Err(format!(
+                    "'{}' and '{}' are both {} {} at {}; filter one of them out",
+                    other.3,
+                    m.name,
+                    self.series,
+                    series,
+                    format_size(m.size)
+                ));
+            }
+            points.push((series, m.size, value, &m.name));
+        }
+        if points.is_empty() {
+            return Err(format!(
+                "no measurements with {} to chart",
+                self.metric.label()
+            ));
+        }
+
+        let mut sizes: Vec<usize> = points.iter().map(|p| p.1).collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+        let mut series: Vec<(String, Vec<Option<f64>>)> = Vec::new();
+        for (name, size, value, _) in points {
+            let index = match series.iter().position(|(s, _)| *s == name) {
+                Some(index) => index,
+                None => {
+                    series.push((name, vec![None; sizes.len()]));
+                    series.len() - 1
+                }
+            };
+            let slot = sizes.binary_search(&size).expect("size was collected");
+            series[index].1[slot] = Some(value);
+        }
+        Ok(Data { sizes, series })
+    }
+
+    fn frame(&self, data: &Data) -> Frame {
+        let max = data
+            .series
+            .iter()
+            .flat_map(|(_, values)| values.iter().flatten())
+            .fold(0.0f64, |a, &b| a.max(b));
+        let y_step = nice_step(max / 5.0);
+        let y_max = ((max / y_step).ceil() * y_step).max(y_step);
+
+        // The legend sits to the right of the plot, sized for the longest name
+        let longest = data.series.iter().map(|(s, _)| s.chars().count()).max();
+        let legend = 36.0 + 7.0 * longest.unwrap_or(0) as f64;
+        let (left, right) = (72.0, self.width as f64 - 16.0 - legend);
+        Frame {
+            left,
+            right,
+            top: 44.0,
+            bottom: self.height as f64 - 52.0,
+            y_max,
+            y_step,
+            band: (right - left) / data.sizes.len() as f64,
+        }
+    }
+
+    fn write_svg(&self, out: &mut String, data: &Data) -> fmt::Result {
+        let frame = self.frame(data);
+        let (width, height) = (self.width, self.height);
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" font-size="12">"#
+        )?;
+        writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#)?;
+        writeln!(
+            out,
+            r#"<text x="{:.1}" y="24" text-anchor="middle" font-size="16" font-weight="bold">{}</text>"#,
+            width as f64 / 2.0,
+            escape(&self.title)
+        )?;
+
+        // Grid lines and y tick labels
+        let ticks = (frame.y_max / frame.y_step).round() as usize;
+        for i in 0..=ticks {
+            let value = i as f64 * frame.y_step;
+            let y = frame.y(value);
+            writeln!(
+                out,
+                r##"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="#e0e0e0"/>"##,
+                frame.left, frame.right
+            )?;
+            writeln!(
+                out,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+                frame.left - 8.0,
+                y + 4.0,
+                tick_label(value, frame.y_step, frame.y_max)
+            )?;
+        }
+        // Axes and x tick labels
+        writeln!(
+            out,
+            r##"<path d="M{:.1} {:.1}V{:.1}H{:.1}" fill="none" stroke="#333"/>"##,
+            frame.left, frame.top, frame.bottom, frame.right
+        )?;
+        for (i, &size) in data.sizes.iter().enumerate() {
+            writeln!(
+                out,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+                frame.x(i),
+                frame.bottom + 18.0,
+                format_size(size)
+            )?;
+        }
+        writeln!(
+            out,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">read size</text>"#,
+            (frame.left + frame.right) / 2.0,
+            frame.bottom + 40.0
+        )?;
+        writeln!(
+            out,
+            r#"<text transform="translate(18 {:.1}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            (frame.top + frame.bottom) / 2.0,
+            escape(self.metric.label())
+        )?;
+
+        for (j, (name, _)) in data.series.iter().enumerate() {
+            let color = PALETTE[j % PALETTE.len()];
+            match self.kind {
+                ChartKind::Line => self.write_line(out, &frame, data, j, color)?,
+                ChartKind::Bar => self.write_bars(out, &frame, data, j, color)?,
+            }
+            // Legend entry
+            let y = frame.top + 18.0 * j as f64;
+            writeln!(
+                out,
+                r#"<rect x="{:.1}" y="{:.1}" width="12" height="12" fill="{color}"/>"#,
+                frame.right + 20.0,
+                y
+            )?;
+            writeln!(
+                out,
+                r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                frame.right + 38.0,
+                y + 10.0,
+                escape(name)
+            )?;
+        }
+        writeln!(out, "</svg>")
+    }
+
+    /// A series as a polyline, broken where a size was not measured, with a
+    /// marker per point
+    fn write_line(
+        &self,
+        out: &mut String,
+        frame: &Frame,
+        data: &Data,
+        series: usize,
+        color: &str,
+    ) -> fmt::Result {
+        let (name, values) = &data.series[series];
+        let points: Vec<Option<(f64, f64)>> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v.map(|v| (frame.x(i), frame.y(v))))
+            .collect();
+        for run in points.split(Option::is_none).filter(|run| run.len() > 1) {
+            let coords: Vec<String> = run
+                .iter()
+                .flatten()
+                .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+                .collect();
+            writeln!(
+                out,
+                r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="2"/>"#,
+                coords.join(" ")
+            )?;
+        }
+        for (i, point) in points.iter().enumerate() {
+            let (Some((x, y)), Some(value)) = (point, values[i]) else {
+                continue;
+            };
+            writeln!(
+                out,
+                r#"<circle cx="{x:.1}" cy="{y:.1}" r="3.5" fill="{color}"><title>{}</title></circle>"#,
+                self.point_title(name, data.sizes[i], value)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// A series as one bar per size, side by side with the other series
+    fn write_bars(
+        &self,
+        out: &mut String,
+        frame: &Frame,
+        data: &Data,
+        series: usize,
+        color: &str,
+    ) -> fmt::Result {
+        let (name, values) = &data.series[series];
+        let bar = frame.band * 0.8 / data.series.len() as f64;
+        for (i, value) in values.iter().enumerate() {
+            let Some(value) = *value else {
+                continue;
+            };
+            let x = frame.x(i) - frame.band * 0.4 + bar * series as f64;
+            let y = frame.y(value);
+            writeln!(
+                out,
+                r#"<rect x="{x:.1}" y="{y:.1}" width="{:.1}" height="{:.1}" fill="{color}"><title>{}</title></rect>"#,
+                bar,
+                frame.bottom - y,
+                self.point_title(name, data.sizes[i], value)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Hover text, e.g. `io_uring 4KB: 23.1 mean latency (µs)`
+    fn point_title(&self, series: &str, size: usize, value: f64) -> String {
+        escape(&format!(
+            "{} {}: {} {}",
+            series,
+            format_size(size),
+            format_value(value),
+            self.metric.label()
+        ))
+    }
+}
+
+/// A self-contained HTML page showing SVG `charts` one below another
+pub fn html_page(title: &str, charts: &[String]) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>body {{ font-family: sans-serif; margin: 2em; }} figure {{ margin: 0 0 2em; }}</style>\n\
+         </head>\n<body>\n",
+        escape(title)
+    );
+    for chart in charts {
+        page.push_str("<figure>\n");
+        page.push_str(chart);
+        page.push_str("</figure>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// 1, 2 or 5 times a power of ten, at least `raw`
+fn nice_step(raw: f64) -> f64 {
+    if raw <= 0.0 || !raw.is_finite() {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .find(|&m| m * magnitude >= raw)
+        .unwrap_or(10.0);
+    step * magnitude
+}
+
+/// A y tick label with just enough decimals for the step, e.g. `0.5`, `20k`
+fn tick_label(value: f64, step: f64, max: f64) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let (scale, suffix) = if max >= 1e6 {
+        (1e6, "M")
+    } else if max >= 1e4 {
+        (1e3, "k")
+    } else {
+        (1.0, "")
+    };
+    let step = step / scale;
+    let decimals = if step >= 1.0 {
+        0
+    } else {
+        (-step.log10() - 1e-9).ceil() as usize
+    };
+    format!("{:.*}{}", decimals, value / scale, suffix)
+}
+
+/// Three significant digits, roughly
+fn format_value(value: f64) -> String {
+    if value >= 100.0 {
+        format!("{:.0}", value)
+    } else if value >= 10.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// Escape text for SVG/HTML content and attributes
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::LatencySummary;
+    use std::time::Duration;
+
+    #[test]
+    fn nice_steps() {
+        for (raw, step) in [
+            (0.7, 1.0),
+            (1.0, 1.0),
+            (1.2, 2.0),
+            (3.0, 5.0),
+            (7.5, 10.0),
+            (12.0, 20.0),
+            (450.0, 500.0),
+            (0.03, 0.05),
+            (2e6, 2e6),
+        ] {
+            let actual = nice_step(raw);
+            assert!((actual - step).abs() < step * 1e-9, "{} -> {}", raw, actual);
+        }
+        for degenerate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(nice_step(degenerate), 1.0);
+        }
+    }
+
+    #[test]
+    fn tick_labels() {
+        assert_eq!(tick_label(0.0, 0.5, 2.0), "0");
+        assert_eq!(tick_label(1.5, 0.5, 2.0), "1.5");
+        assert_eq!(tick_label(0.05, 0.05, 0.2), "0.05");
+        assert_eq!(tick_label(40.0, 20.0, 100.0), "40");
+        assert_eq!(tick_label(20_000.0, 10_000.0, 50_000.0), "20k");
+        assert_eq!(tick_label(2_500.0, 500.0, 10_000.0), "2.5k");
+        assert_eq!(tick_label(3e6, 1e6, 5e6), "3M");
+    }
+
+    fn measurement(name: &str, backend: &str, size: usize, mean: f64) -> Measurement {
+        Measurement {
+            group: "group".to_string(),
+            name: name.to_string(),
+            backend: backend.to_string(),
+            size,
+            operations: 10,
+            elapsed: Duration::from_secs(1),
+            latency: Some(LatencySummary {
+                count: 10,
+                mean,
+                ..LatencySummary::default()
+            }),
+            ..Measurement::default()
+        }
+    }
+
+    #[test]
+    fn nothing_to_chart() {
+        let chart = Chart::new("empty");
+        assert_eq!(
+            chart.render(&[]).unwrap_err(),
+            "no measurements with mean latency (µs) to chart"
+        );
+        let mut no_latency = measurement("a", "pread", 4096, 1.0);
+        no_latency.latency = None;
+        assert!(chart.render(&[no_latency]).is_err());
+    }
+
+    #[test]
+    fn single_and_zero_points() {
+        for kind in [ChartKind::Line, ChartKind::Bar] {
+            let mut chart = Chart::new("one");
+            chart.kind = kind;
+            for mean in [0.0, 23_100.0] {
+                let svg = chart
+                    .render(&[measurement("a", "pread", 4096, mean)])
+                    .unwrap();
+                assert!(!svg.contains("NaN") && !svg.contains("inf"), "{}", svg);
+                assert!(svg.contains(">4KB</text>"));
+            }
+        }
+    }
+
+    #[test]
+    fn series_and_gaps() {
+        let chart = Chart::new("two");
+        let measurements = [
+            measurement("a", "pread", 4096, 20_000.0),
+            measurement("b", "pread", 16384, 40_000.0),
+            measurement("c", "io_uring", 4096, 10_000.0),
+        ];
+        let data = chart.data(&measurements).unwrap();
+        assert_eq!(data.sizes, [4096, 16384]);
+        assert_eq!(
+            data.series,
+            [
+                ("pread".to_string(), vec![Some(20.0), Some(40.0)]),
+                ("io_uring".to_string(), vec![Some(10.0), None]),
+            ]
+        );
+        let svg = chart.render(&measurements).unwrap();
+        // io_uring has one point, so only pread gets a line
+        assert_eq!(svg.matches("<polyline").count(), 1);
+        assert_eq!(svg.matches("<circle").count(), 3);
+    }
+
+    #[test]
+    fn duplicate_points_need_a_filter() {
+        let mut chart = Chart::new("dup");
+        let measurements = [
+            measurement("a", "pread", 4096, 1.0),
+            measurement("b", "pread", 4096, 2.0),
+        ];
+        assert_eq!(
+            chart.render(&measurements).unwrap_err(),
+            "'a' and 'b' are both backend pread at 4KB; filter one of them out"
+        );
+        chart.filters.push((Field::Param("x".to_string()), vec![]));
+        assert!(chart.render(&measurements).is_err());
+    }
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(escape(r#"<a & "b">"#), "&lt;a &amp; &quot;b&quot;&gt;");
+        let page = html_page("p99 <fast>", &["<svg/>".to_string()]);
+        assert!(page.contains("<title>p99 &lt;fast&gt;</title>"));
+        assert!(page.contains("<figure>\n<svg/></figure>"));
+    }
+}
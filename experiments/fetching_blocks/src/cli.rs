@@ -0,0 +1,511 @@
+//! Command-line parsing for the `fetching_blocks` binary.
+//!
+//! Hand-rolled rather than pulling in an argument parser: the flags are few
+//! and all take a single value. Every flag is also a job-file key, and both
+//! are applied through `Job::set`.
+
+use std::path::PathBuf;
+
+use fetching_blocks::chart::{Chart, Field};
+use fetching_blocks::{format_size, parse_size, PolicyKind};
+
+use crate::compare::CompareOptions;
+use crate::job::Job;
+
+pub const USAGE: &str = "\
+Usage: fetching_blocks [OPTIONS] <PATH>
+       fetching_blocks --job <FILE>
+       fetching_blocks compare [--threshold <PCT>] [--alpha <P>] <BASELINE> <CURRENT>
+       fetching_blocks chart [CHART OPTIONS] <RESULTS>
+       fetching_blocks simulate [SIMULATE OPTIONS] <TRACE>
+
+Issue reads against a file or block device and print throughput and latency
+for every combination of read size, backend, cache mode and queue depth.
+
+Options:
+  --job <FILE>         Run the jobs described in FILE instead of a single
+                       job built from these flags; see jobs/example.job
+  --sizes <LIST>       Read sizes, e.g. 512,2k,4k (default: derived from the
+                       device's block geometry)
+  --pattern <PATTERN>  sequential, uniform, zipfian or zipfian:<theta>
+                       (default: uniform)
+  --backend <LIST>     pread, preadv, mmap or io_uring (default: pread);
+                       io_uring keeps all reads in flight on one ring, the
+                       others use one thread per outstanding read
+  --qd <LIST>          Queue depths, e.g. 1,4,16 (default: 1)
+  --duration <TIME>    How long each run lasts, e.g. 500ms, 10s, 1m
+                       (default: 5s)
+  --reads <N>          Stop each run after N reads instead of a duration
+  --cache <LIST>       direct (bypass the page cache), buffered (as is),
+                       cold (evict the file first) or warm (read it all
+                       first) (default: direct)
+  --block-size <SIZE>  Offsets are multiples of this (default: the device's
+                       fetch unit)
+  --seed <N>           Seed for the offset generator (default: 42)
+  --json <FILE>        Also write every run's results to FILE as JSON
+  --csv <FILE>         Also write every run's results to FILE as CSV
+  -h, --help           Print this help
+
+compare loads two JSON result files (from --json or the benchmarks), matches
+measurements by group and name, and reports mean latency and IOPS changes.
+A change is a regression when Welch's t-test on latency is significant and
+latency rises or IOPS falls by more than the threshold; measurements without
+latency only need IOPS to fall that far. Exits with 1 if anything regressed.
+  --threshold <PCT>    Smallest change that counts, e.g. 5 or 5% (default: 5%)
+  --alpha <P>          Significance level (default: 0.01)
+
+chart draws a JSON result file as an SVG chart of one metric against read
+size, or as an HTML page holding the chart when --output ends in .html.
+  --kind <KIND>        line or bar (default: line)
+  --metric <METRIC>    mean, p50 or p99 latency, iops or bandwidth
+                       (default: mean)
+  --series <FIELD>     One series per value of backend, cache, qd, group or
+                       param:<name>, e.g. param:pattern (default: backend)
+  --only <FIELD>=<LIST>
+                       Keep measurements whose FIELD is in LIST, e.g.
+                       --only group=subblock_reads; may be repeated
+  --title <TEXT>       Chart title (default: the metric)
+  --output <FILE>      Where to write the chart (default: standard output)
+
+simulate replays a block access trace through cache policies at many
+capacities and prints each policy's miss ratio per capacity. TRACE has one
+read per line, <file> <offset> <length>, or is - for standard input.
+  --block-size <SIZE>  Cache block size (default: 4k)
+  --capacities <LIST>  Cache sizes, e.g. 1M,64M,1G (default: 16 blocks to
+                       1M blocks, doubling)
+  --policy <LIST>      lru, clock or arc (default: all)
+  --csv <FILE>         Also write every policy and capacity to FILE as CSV";
+
+/// Where to save results besides the console
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub json: Option<PathBuf>,
+    pub csv: Option<PathBuf>,
+}
+
+/// A chart of a saved result file
+#[derive(Debug, Clone)]
+pub struct ChartOptions {
+    pub results: PathBuf,
+    /// Standard output when `None`
+    pub output: Option<PathBuf>,
+    pub chart: Chart,
+}
+
+/// A cache simulation of a trace file
+#[derive(Debug, Clone)]
+pub struct SimulateOptions {
+    /// `-` for standard input
+    pub trace: PathBuf,
+    pub block_size: u64,
+    /// In blocks
+    pub capacities: Vec<usize>,
+    pub policies: Vec<PolicyKind>,
+    pub csv: Option<PathBuf>,
+}
+
+pub enum Command {
+    Run(Job, Output),
+    RunFile(PathBuf, Output),
+    Compare(CompareOptions),
+    Chart(ChartOptions),
+    Simulate(SimulateOptions),
+    Help,
+}
+
+/// Parse the arguments after the program name
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("compare") => {
+            args.next();
+            return parse_compare_args(args);
+        }
+        Some("chart") => {
+            args.next();
+            return parse_chart_args(args);
+        }
+        Some("simulate") => {
+            args.next();
+            return parse_simulate_args(args);
+        }
+        _ => {}
+    }
+    let mut path = None;
+    let mut job_file = None;
+    let mut job = Job::new("cli");
+    let mut output = Output::default();
+    let mut flags_given = false;
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            if path.replace(PathBuf::from(&arg)).is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            continue;
+        };
+        let (flag, value) = flag_value(flag, &mut args)?;
+        match flag {
+            "job" => job_file = Some(PathBuf::from(value)),
+            "json" => output.json = Some(PathBuf::from(value)),
+            "csv" => output.csv = Some(PathBuf::from(value)),
+            _ => {
+                // The path is positional on the command line
+                if flag == "path" || !Job::KEYS.contains(&flag) {
+                    return Err(format!("unknown option '--{}'", flag));
+                }
+                job.set(flag, &value)?;
+                flags_given = true;
+            }
+        }
+    }
+
+    match (job_file, path) {
+        (Some(_), _) if flags_given => Err("--job cannot be combined with job options".to_string()),
+        (Some(_), Some(path)) => Err(format!("unexpected argument '{}'", path.display())),
+        (Some(file), None) => Ok(Command::RunFile(file, output)),
+        (None, path) => {
+            job.path = Some(path.ok_or("missing <PATH>")?);
+            Ok(Command::Run(job, output))
+        }
+    }
+}
+
+/// Arguments after `compare`
+fn parse_compare_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args;
+    let mut files = Vec::new();
+    let mut threshold = None;
+    let mut alpha = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            files.push(PathBuf::from(arg));
+            continue;
+        };
+        let (flag, value) = flag_value(flag, &mut args)?;
+        match flag {
+            "threshold" => threshold = Some(parse_percent(&value)?),
+            "alpha" => alpha = Some(parse_probability(&value)?),
+            _ => return Err(format!("unknown option '--{}'", flag)),
+        }
+    }
+
+    let [baseline, current]: [PathBuf; 2] = files
+        .try_into()
+        .map_err(|_| "compare needs <BASELINE> and <CURRENT>".to_string())?;
+    let mut options = CompareOptions::new(baseline, current);
+    options.threshold = threshold.unwrap_or(options.threshold);
+    options.alpha = alpha.unwrap_or(options.alpha);
+    Ok(Command::Compare(options))
+}
+
+/// Arguments after `chart`
+fn parse_chart_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args;
+    let mut results = None;
+    let mut output = None;
+    let mut title = None;
+    let mut chart = Chart::new("");
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            if results.replace(PathBuf::from(&arg)).is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            continue;
+        };
+        let (flag, value) = flag_value(flag, &mut args)?;
+        match flag {
+            "kind" => chart.kind = value.parse()?,
+            "metric" => chart.metric = value.parse()?,
+            "series" => chart.series = value.parse()?,
+            "only" => {
+                let (field, list) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--only needs <FIELD>=<LIST>, got '{}'", value))?;
+                let values = list.split(',').map(|v| v.trim().to_string()).collect();
+                chart.filters.push((field.parse::<Field>()?, values));
+            }
+            "title" => title = Some(value),
+            "output" => output = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option '--{}'", flag)),
+        }
+    }
+
+    chart.title = title.unwrap_or_else(|| format!("{} by read size", chart.metric.label()));
+    Ok(Command::Chart(ChartOptions {
+        results: results.ok_or("chart needs <RESULTS>")?,
+        output,
+        chart,
+    }))
+}
+
+/// Arguments after `simulate`
+fn parse_simulate_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args;
+    let mut trace = None;
+    let mut block_size = 4096;
+    let mut capacities = None;
+    let mut policies = PolicyKind::ALL.to_vec();
+    let mut csv = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        // `-` is the trace, not a flag
+        let Some(flag) = arg.strip_prefix("--") else {
+            if trace.replace(PathBuf::from(&arg)).is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            continue;
+        };
+        let (flag, value) = flag_value(flag, &mut args)?;
+        match flag {
+            "block-size" => {
+                block_size = parse_size(&value)
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| format!("invalid block size '{}'", value))?
+                    as u64
+            }
+            "capacities" => {
+                let sizes = value
+                    .split(',')
+                    .map(|s| parse_size(s).ok_or_else(|| format!("invalid capacity '{}'", s)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                capacities = Some(sizes);
+            }
+            "policy" => {
+                policies = value
+                    .split(',')
+                    .map(|s| s.trim().parse())
+                    .collect::<Result<_, _>>()?
+            }
+            "csv" => csv = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option '--{}'", flag)),
+        }
+    }
+
+    // Capacities are given in bytes and simulated in blocks
+    let capacities = match capacities {
+        Some(sizes) => sizes
+            .into_iter()
+            .map(|size| {
+                let blocks = size as u64 / block_size;
+                if blocks == 0 {
+                    return Err(format!(
+                        "capacity {} is smaller than a block",
+                        format_size(size)
+                    ));
+                }
+                Ok(blocks as usize)
+            })
+            .collect::<Result<_, _>>()?,
+        None => (4..=20).map(|shift| 1 << shift).collect(),
+    };
+    Ok(Command::Simulate(SimulateOptions {
+        trace: trace.ok_or("simulate needs <TRACE>")?,
+        block_size,
+        capacities,
+        policies,
+        csv,
+    }))
+}
+
+/// Split a flag (without its `--`) from its value, given either as
+/// `--flag value` or `--flag=value`
+fn flag_value<'a>(
+    flag: &'a str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<(&'a str, String), String> {
+    match flag.split_once('=') {
+        Some((flag, value)) => Ok((flag, value.to_string())),
+        None => {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("--{} needs a value", flag))?;
+            Ok((flag, value))
+        }
+    }
+}
+
+/// `5`, `5%` or `2.5` percent, as a fraction
+fn parse_percent(s: &str) -> Result<f64, String> {
+    s.trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|p| p.is_finite() && *p >= 0.0)
+        .map(|p| p / 100.0)
+        .ok_or_else(|| format!("invalid percentage '{}'", s))
+}
+
+fn parse_probability(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|p| *p > 0.0 && *p < 1.0)
+        .ok_or_else(|| format!("invalid significance level '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fetching_blocks::chart::Metric;
+    use fetching_blocks::load::Limit;
+    use fetching_blocks::BackendKind;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(e) => e,
+            Ok(_) => panic!("{:?} parsed", args),
+        }
+    }
+
+    #[test]
+    fn run_flags() {
+        let Ok(Command::Run(job, output)) = parse(&[
+            "--sizes",
+            "512,4k",
+            "--backend=pread,io_uring",
+            "--reads",
+            "100",
+            "--json",
+            "out.json",
+            "/dev/nvme0n1",
+        ]) else {
+            panic!("not a run");
+        };
+        assert_eq!(job.path, Some(PathBuf::from("/dev/nvme0n1")));
+        assert_eq!(job.sizes, [512, 4096]);
+        assert_eq!(job.backends, [BackendKind::Pread, BackendKind::IoUring]);
+        assert_eq!(job.limit, Limit::Reads(100));
+        assert_eq!(output.json, Some(PathBuf::from("out.json")));
+        assert_eq!(output.csv, None);
+    }
+
+    #[test]
+    fn job_file() {
+        let Ok(Command::RunFile(file, _)) = parse(&["--job=jobs/example.job"]) else {
+            panic!("not a job file");
+        };
+        assert_eq!(file, PathBuf::from("jobs/example.job"));
+        assert!(matches!(
+            parse(&["--job", "a.job", "--help"]),
+            Ok(Command::Help)
+        ));
+    }
+
+    #[test]
+    fn run_errors() {
+        assert_eq!(error(&[]), "missing <PATH>");
+        assert_eq!(error(&["a", "b"]), "unexpected argument 'b'");
+        assert_eq!(error(&["--sizes"]), "--sizes needs a value");
+        assert_eq!(error(&["--path", "a"]), "unknown option '--path'");
+        assert_eq!(error(&["--bogus=1", "a"]), "unknown option '--bogus'");
+        assert_eq!(
+            error(&["--job", "a.job", "--qd", "4"]),
+            "--job cannot be combined with job options"
+        );
+        assert_eq!(error(&["--job", "a.job", "a"]), "unexpected argument 'a'");
+    }
+
+    #[test]
+    fn compare_flags() {
+        let Ok(Command::Compare(options)) = parse(&[
+            "compare",
+            "--threshold=10%",
+            "--alpha",
+            "0.05",
+            "a.json",
+            "b.json",
+        ]) else {
+            panic!("not a compare");
+        };
+        assert_eq!(options.baseline, PathBuf::from("a.json"));
+        assert_eq!(options.current, PathBuf::from("b.json"));
+        assert_eq!(options.threshold, 0.1);
+        assert_eq!(options.alpha, 0.05);
+
+        assert_eq!(
+            error(&["compare", "a.json"]),
+            "compare needs <BASELINE> and <CURRENT>"
+        );
+        assert_eq!(
+            error(&["compare", "--alpha", "1", "a", "b"]),
+            "invalid significance level '1'"
+        );
+        assert_eq!(
+            error(&["compare", "--threshold", "-5", "a", "b"]),
+            "invalid percentage '-5'"
+        );
+    }
+
+    #[test]
+    fn chart_flags() {
+        let Ok(Command::Chart(options)) = parse(&[
+            "chart",
+            "--metric=iops",
+            "--only",
+            "group=subblock_reads,load",
+            "results.json",
+        ]) else {
+            panic!("not a chart");
+        };
+        assert_eq!(options.results, PathBuf::from("results.json"));
+        assert_eq!(options.output, None);
+        assert_eq!(options.chart.metric, Metric::Iops);
+        assert_eq!(
+            options.chart.filters,
+            [(
+                Field::Group,
+                vec!["subblock_reads".to_string(), "load".to_string()]
+            )]
+        );
+        assert_eq!(error(&["chart"]), "chart needs <RESULTS>");
+        assert_eq!(
+            error(&["chart", "--only", "group", "r.json"]),
+            "--only needs <FIELD>=<LIST>, got 'group'"
+        );
+    }
+
+    #[test]
+    fn simulate_flags() {
+        let Ok(Command::Simulate(options)) = parse(&[
+            "simulate",
+            "--block-size=8k",
+            "--capacities",
+            "64k,1M",
+            "--policy",
+            "lru,arc",
+            "-",
+        ]) else {
+            panic!("not a simulation");
+        };
+        assert_eq!(options.trace, PathBuf::from("-"));
+        assert_eq!(options.block_size, 8192);
+        assert_eq!(options.capacities, [8, 128]);
+        assert_eq!(options.policies, [PolicyKind::Lru, PolicyKind::Arc]);
+
+        assert_eq!(
+            error(&["simulate", "--capacities", "2k", "t"]),
+            "capacity 2KB is smaller than a block"
+        );
+        assert_eq!(
+            error(&["simulate", "--block-size", "0", "t"]),
+            "invalid block size '0'"
+        );
+    }
+}
@@ -0,0 +1,522 @@
+//! `fetching_blocks compare`: regressions between two saved result files.
+//!
+//! Measurements are matched by group and name, which encode the whole
+//! configuration (backend, cache mode, queue depth, size, ...). A change
+//! counts when Welch's t-test on the mean latency says it is unlikely to be
+//! noise (p below `alpha`) and it moves mean latency or IOPS by more than
+//! `threshold`. Measurements without a latency distribution (whole-span
+//! timings) cannot be tested, so their IOPS change is judged against
+//! `threshold` alone.
+
+use std::path::PathBuf;
+
+use fetching_blocks::json::Json;
+use fetching_blocks::report::LatencySummary;
+use fetching_blocks::{Measurement, Report};
+
+/// What to compare and how strict to be
+#[derive(Debug, Clone)]
+pub struct CompareOptions {
+    pub baseline: PathBuf,
+    pub current: PathBuf,
+    /// Smallest relative change that counts, e.g. 0.05 for 5%
+    pub threshold: f64,
+    /// Significance level for the t-test
+    pub alpha: f64,
+}
+
+impl CompareOptions {
+    pub fn new(baseline: PathBuf, current: PathBuf) -> Self {
+        CompareOptions {
+            baseline,
+            current,
+            threshold: 0.05,
+            alpha: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Regression,
+    Improvement,
+    Unchanged,
+}
+
+impl Verdict {
+    fn name(&self) -> &'static str {
+        match self {
+            Verdict::Regression => "REGRESSION",
+            Verdict::Improvement => "improvement",
+            Verdict::Unchanged => "~",
+        }
+    }
+}
+
+/// Compare the two files, printing a table; returns whether anything regressed
+pub fn compare(options: &CompareOptions) -> Result<bool, String> {
+    let load =
+        |path: &PathBuf| Report::load(path).map_err(|e| format!("{}: {}", path.display(), e));
+    let baseline = load(&options.baseline)?;
+    let current = load(&options.current)?;
+
+    println!(
+        "baseline {} ({} measurements)",
+        options.baseline.display(),
+        baseline.measurements.len()
+    );
+    println!(
+        "current  {} ({} measurements)",
+        options.current.display(),
+        current.measurements.len()
+    );
+    print_environment_changes(&baseline, &current);
+    println!(
+        "threshold {:.1}%, alpha {}",
+        options.threshold * 100.0,
+        options.alpha
+    );
+    println!();
+
+    let key = |m: &Measurement| (m.group.clone(), m.name.clone());
+    let pairs: Vec<(&Measurement, &Measurement)> = current
+        .measurements
+        .iter()
+        .filter_map(|cur| {
+            let base = baseline.measurements.iter().find(|b| key(b) == key(cur))?;
+            Some((base, cur))
+        })
+        .collect();
+
+    let width = pairs
+        .iter()
+        .map(|(_, cur)| cur.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "{:<width$} {:>12} {:>12} {:>8} {:>11} {:>11} {:>8} {:>8} {:>9}  verdict",
+        "name",
+        "base_mean_us",
+        "cur_mean_us",
+        "mean_%",
+        "base_IOPS",
+        "cur_IOPS",
+        "IOPS_%",
+        "p99_%",
+        "p_value",
+    );
+
+    let mut counts = [0usize; 3];
+    for (base, cur) in &pairs {
+        let verdict = judge(base, cur, options);
+        counts[verdict as usize] += 1;
+        print_row(base, cur, verdict, width);
+    }
+
+    let only = |a: &Report, b: &Report| {
+        a.measurements
+            .iter()
+            .filter(|m| !b.measurements.iter().any(|n| key(n) == key(m)))
+            .count()
+    };
+    println!();
+    println!(
+        "{} regressions, {} improvements, {} unchanged; {} only in baseline, {} only in current",
+        counts[Verdict::Regression as usize],
+        counts[Verdict::Improvement as usize],
+        counts[Verdict::Unchanged as usize],
+        only(&baseline, &current),
+        only(&current, &baseline),
+    );
+    Ok(counts[Verdict::Regression as usize] > 0)
+}
+
+/// Relative change from `base` to `cur`
+///
+/// `None` when no percentage describes it: growth from 0, or a rate of a
+/// measurement that took no time.
+fn change(base: f64, cur: f64) -> Option<f64> {
+    if base == 0.0 {
+        return (cur == 0.0).then_some(0.0);
+    }
+    Some((cur - base) / base).filter(|change| change.is_finite())
+}
+
+fn judge(base: &Measurement, cur: &Measurement, options: &CompareOptions) -> Verdict {
+    // Growth from 0 is beyond any threshold; an undefined rate is no change
+    let change = |base: f64, cur: f64| {
+        change(base, cur).unwrap_or(if cur > base { f64::INFINITY } else { 0.0 })
+    };
+    let iops = change(base.iops(), cur.iops());
+    // Nothing to test; the threshold alone decides
+    let Some((base_latency, cur_latency)) = base.latency.zip(cur.latency) else {
+        return if iops < -options.threshold {
+            Verdict::Regression
+        } else if iops > options.threshold {
+            Verdict::Improvement
+        } else {
+            Verdict::Unchanged
+        };
+    };
+    let significant = welch_p_value(&base_latency, &cur_latency).is_some_and(|p| p < options.alpha);
+    if !significant {
+        return Verdict::Unchanged;
+    }
+    let mean = change(base_latency.mean, cur_latency.mean);
+    if mean > options.threshold || iops < -options.threshold {
+        Verdict::Regression
+    } else if mean < -options.threshold || iops > options.threshold {
+        Verdict::Improvement
+    } else {
+        Verdict::Unchanged
+    }
+}
+
+fn print_row(base: &Measurement, cur: &Measurement, verdict: Verdict, width: usize) {
+    let dash = || "-".to_string();
+    let us = |l: Option<LatencySummary>| l.map_or_else(dash, |l| format!("{:.1}", l.mean / 1e3));
+    let percent = |change: Option<f64>| change.map_or_else(dash, |c| format!("{:+.1}", c * 100.0));
+    let latencies = base.latency.zip(cur.latency);
+    println!(
+        "{:<width$} {:>12} {:>12} {:>8} {:>11.0} {:>11.0} {:>8} {:>8} {:>9}  {}",
+        cur.name,
+        us(base.latency),
+        us(cur.latency),
+        latencies.map_or_else(dash, |(b, c)| percent(change(b.mean, c.mean))),
+        base.iops(),
+        cur.iops(),
+        percent(change(base.iops(), cur.iops())),
+        latencies.map_or_else(dash, |(b, c)| percent(change(b.p99 as f64, c.p99 as f64))),
+        latencies
+            .and_then(|(b, c)| welch_p_value(&b, &c))
+            .map_or_else(dash, |p| format!("{:.2e}", p)),
+        verdict.name(),
+    );
+}
+
+/// Print every environment field that differs between the two reports
+///
+/// A kernel, scheduler or device change explains a shift better than any
+/// statistic, so it is shown before the table.
+fn print_environment_changes(baseline: &Report, current: &Report) {
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    flatten("", &baseline.environment.to_json(), &mut before);
+    flatten("", &current.environment.to_json(), &mut after);
+
+    let mut changes = Vec::new();
+    for (key, old) in &before {
+        let new = after
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str());
+        if new != Some(old.as_str()) {
+            changes.push(format!("  {}: {} -> {}", key, old, new.unwrap_or("(none)")));
+        }
+    }
+    for (key, new) in &after {
+        if !before.iter().any(|(k, _)| k == key) {
+            changes.push(format!("  {}: (none) -> {}", key, new));
+        }
+    }
+
+    if changes.is_empty() {
+        println!("environment unchanged");
+    } else {
+        println!("environment changed:");
+        for change in changes {
+            println!("{}", change);
+        }
+    }
+}
+
+/// Leaves of a JSON value as `path.to.leaf` / value pairs
+fn flatten(prefix: &str, json: &Json, out: &mut Vec<(String, String)>) {
+    match json {
+        Json::Object(fields) => {
+            for (key, value) in fields {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&path, value, out);
+            }
+        }
+        Json::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, i), item, out);
+            }
+        }
+        Json::String(s) => out.push((prefix.to_string(), s.clone())),
+        Json::Null => out.push((prefix.to_string(), "null".to_string())),
+        leaf => out.push((prefix.to_string(), leaf.to_string())),
+    }
+}
+
+/// Two-sided p-value of Welch's t-test on the two mean latencies
+///
+/// `None` when either side has fewer than two samples, or neither varies and
+/// the means are equal. When neither varies but the means differ, there is
+/// no noise to explain the difference and the p-value is 0.
+fn welch_p_value(a: &LatencySummary, b: &LatencySummary) -> Option<f64> {
+    if a.count < 2 || b.count < 2 {
+        return None;
+    }
+    let (na, nb) = (a.count as f64, b.count as f64);
+    let (va, vb) = (a.stddev * a.stddev / na, b.stddev * b.stddev / nb);
+    if va + vb == 0.0 {
+        return (a.mean != b.mean).then_some(0.0);
+    }
+    let t = (a.mean - b.mean) / (va + vb).sqrt();
+    // Welch–Satterthwaite degrees of freedom
+    let df = (va + vb).powi(2) / (va * va / (na - 1.0) + vb * vb / (nb - 1.0));
+    Some(student_t_two_sided(t, df))
+}
+
+/// P(|T| >= |t|) for Student's t with `df` degrees of freedom
+fn student_t_two_sided(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+///
+/// Continued-fraction evaluation (Lentz's method), using the symmetry
+/// I_x(a, b) = 1 - I_{1-x}(b, a) where the fraction converges slowly.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        // Even step, then odd step, of the continued fraction
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// ln Γ(x) for x > 0 (Lanczos approximation, g = 7)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, &c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn ln_gamma_known_values() {
+        close(ln_gamma(1.0), 0.0, 1e-12);
+        close(ln_gamma(2.0), 0.0, 1e-12);
+        close(ln_gamma(5.0), 24f64.ln(), 1e-12);
+        close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), 1e-12);
+        close(ln_gamma(0.1), 9.513_507_698_668_732f64.ln(), 1e-10);
+    }
+
+    #[test]
+    fn incomplete_beta_known_values() {
+        assert_eq!(incomplete_beta(0.0, 2.0, 3.0), 0.0);
+        assert_eq!(incomplete_beta(1.0, 2.0, 3.0), 1.0);
+        // I_x(1, 1) = x and I_x(a, 1) = x^a
+        close(incomplete_beta(0.3, 1.0, 1.0), 0.3, 1e-12);
+        close(incomplete_beta(0.6, 3.0, 1.0), 0.216, 1e-12);
+        // I_x(a, b) = 1 - I_{1-x}(b, a)
+        close(
+            incomplete_beta(0.2, 2.5, 4.0),
+            1.0 - incomplete_beta(0.8, 4.0, 2.5),
+            1e-12,
+        );
+    }
+
+    #[test]
+    fn student_t_matches_tables() {
+        // Two-sided critical values from a t-distribution table
+        for (t, df, p) in [
+            (12.706, 1.0, 0.05),
+            (6.314, 1.0, 0.10),
+            (4.303, 2.0, 0.05),
+            (2.228, 10.0, 0.05),
+            (1.812, 10.0, 0.10),
+            (3.169, 10.0, 0.01),
+            (2.086, 20.0, 0.05),
+            (2.042, 30.0, 0.05),
+            (2.660, 60.0, 0.01),
+            (1.960, 1e6, 0.05),
+            (3.291, 1e6, 0.001),
+        ] {
+            close(student_t_two_sided(t, df), p, p * 0.01);
+            close(student_t_two_sided(-t, df), p, p * 0.01);
+        }
+        close(student_t_two_sided(0.0, 10.0), 1.0, 1e-12);
+    }
+
+    fn summary(count: u64, mean: f64, stddev: f64) -> LatencySummary {
+        LatencySummary {
+            count,
+            mean,
+            stddev,
+            min: 0,
+            p50: mean as u64,
+            p90: mean as u64,
+            p99: mean as u64,
+            p999: mean as u64,
+            max: mean as u64,
+        }
+    }
+
+    /// A run of `count` reads with the given latency, at one read per mean
+    /// latency
+    fn measurement(count: u64, mean: f64, stddev: f64) -> Measurement {
+        Measurement {
+            group: "group".to_string(),
+            name: "name".to_string(),
+            operations: count,
+            elapsed: Duration::from_nanos((count as f64 * mean) as u64),
+            latency: Some(summary(count, mean, stddev)),
+            ..Measurement::default()
+        }
+    }
+
+    fn verdict(base: &Measurement, cur: &Measurement) -> Verdict {
+        let options = CompareOptions::new(PathBuf::new(), PathBuf::new());
+        judge(base, cur, &options)
+    }
+
+    #[test]
+    fn welch_p_value_zero_variance() {
+        let a = summary(100, 1000.0, 0.0);
+        assert_eq!(welch_p_value(&a, &a), None);
+        assert_eq!(welch_p_value(&a, &summary(100, 10_000.0, 0.0)), Some(0.0));
+        assert_eq!(welch_p_value(&a, &summary(1, 10_000.0, 0.0)), None);
+    }
+
+    #[test]
+    fn judge_synthetic_summaries() {
+        let base = measurement(10_000, 20_000.0, 2_000.0);
+        assert_eq!(verdict(&base, &base), Verdict::Unchanged);
+        // 20% slower with plenty of samples
+        let slower = measurement(10_000, 24_000.0, 2_000.0);
+        assert_eq!(verdict(&base, &slower), Verdict::Regression);
+        assert_eq!(verdict(&slower, &base), Verdict::Improvement);
+        // Significant, but within the 5% threshold
+        let slightly = measurement(10_000, 20_400.0, 2_000.0);
+        assert_eq!(verdict(&base, &slightly), Verdict::Unchanged);
+        // Beyond the threshold, but too noisy to be significant
+        let noisy = measurement(5, 30_000.0, 20_000.0);
+        assert_eq!(verdict(&base, &noisy), Verdict::Unchanged);
+        // Constant latencies that differ tenfold
+        let fast = measurement(100, 1_000.0, 0.0);
+        let slow = measurement(100, 10_000.0, 0.0);
+        assert_eq!(verdict(&fast, &slow), Verdict::Regression);
+        assert_eq!(verdict(&slow, &fast), Verdict::Improvement);
+        // Without distributions, the IOPS change is judged on the threshold
+        let untested = |m: &Measurement| Measurement {
+            latency: None,
+            ..m.clone()
+        };
+        assert_eq!(
+            verdict(&untested(&base), &untested(&slower)),
+            Verdict::Regression
+        );
+        assert_eq!(
+            verdict(&untested(&slower), &untested(&base)),
+            Verdict::Improvement
+        );
+        assert_eq!(
+            verdict(&untested(&base), &untested(&slightly)),
+            Verdict::Unchanged
+        );
+    }
+
+    #[test]
+    fn change_from_zero() {
+        assert_eq!(change(100.0, 150.0), Some(0.5));
+        assert_eq!(change(0.0, 0.0), Some(0.0));
+        assert_eq!(change(0.0, 5.0), None);
+        assert_eq!(change(f64::NAN, 5.0), None);
+
+        let idle = Measurement {
+            elapsed: Duration::from_secs(1),
+            ..Measurement::default()
+        };
+        assert_eq!(verdict(&idle, &idle), Verdict::Unchanged);
+        let busy = Measurement {
+            latency: None,
+            ..measurement(100, 1_000.0, 0.0)
+        };
+        assert_eq!(verdict(&idle, &busy), Verdict::Improvement);
+        assert_eq!(verdict(&busy, &idle), Verdict::Regression);
+    }
+}
@@ -0,0 +1,174 @@
+//! Discovering the block geometry of the device behind a file.
+//!
+//! `BLOCK_SIZE = 4096` is a guess: drives report 512-byte or 4KB logical
+//! blocks, may use larger physical blocks internally (512e drives), and may
+//! advertise an optimal I/O size. The benchmarks read these from the kernel
+//! instead so the read-size sweep brackets the device's real fetch unit.
+
+use std::io;
+use std::path::Path;
+
+/// Block sizes that govern how reads against one file reach the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockGeometry {
+    /// Smallest unit the device can address (`queue/logical_block_size`)
+    pub logical_block_size: usize,
+    /// Smallest unit the device writes without read-modify-write
+    /// (`queue/physical_block_size`)
+    pub physical_block_size: usize,
+    /// Preferred request size, if the device reports one (`queue/optimal_io_size`)
+    pub optimal_io_size: Option<usize>,
+    /// Filesystem block size (`statfs.f_bsize`)
+    pub fs_block_size: usize,
+    /// Preferred I/O size the filesystem reports for the file (`st_blksize`)
+    pub preferred_io_size: usize,
+    /// Kernel name of the backing block device (e.g. `nvme0n1`), when found
+    pub device: Option<String>,
+}
+
+impl BlockGeometry {
+    /// The unit a single read is expected to fetch from the device
+    pub fn fetch_unit(&self) -> usize {
+        self.physical_block_size.max(self.logical_block_size)
+    }
+
+    /// Read sizes for a sub-block sweep around the fetch unit
+    ///
+    /// Quarter steps up to one fetch unit (the original 1KB/2KB/3KB/4KB table
+    /// for 4KB blocks), the logical block size, two fetch units, and the
+    /// optimal I/O size when it is larger still.
+    pub fn read_sizes(&self) -> Vec<usize> {
+        let unit = self.fetch_unit();
+        let mut sizes: Vec<usize> = (1..=4).map(|q| unit * q / 4).collect();
+        sizes.push(self.logical_block_size);
+        sizes.push(unit * 2);
+        if let Some(optimal) = self.optimal_io_size {
+            sizes.push(optimal);
+        }
+        sizes.retain(|&size| size > 0);
+        sizes.sort_unstable();
+        sizes.dedup();
+        sizes
+    }
+}
+
+/// Detect the block geometry for the file (or directory) at `path`
+///
+/// Combines `statx`/`statfs` on the file with the sysfs queue attributes of
+/// the block device holding it, or of the device itself when `path` is a
+/// block device node. Files on devices without a sysfs entry
+/// (tmpfs, overlayfs, network filesystems) fall back to the direct I/O
+/// alignment as the logical block size and the filesystem block size as the
+/// physical one.
+#[cfg(target_os = "linux")]
+pub fn block_geometry(path: &Path) -> io::Result<BlockGeometry> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut stx: libc::statx = unsafe { std::mem::zeroed() };
+    let rc = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            0,
+            libc::STATX_BASIC_STATS | libc::STATX_DIOALIGN,
+            &mut stx,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut sfs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut sfs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fs_block_size = sfs.f_bsize as usize;
+
+    let dio_align = if stx.stx_mask & libc::STATX_DIOALIGN != 0 {
+        stx.stx_dio_offset_align as usize
+    } else {
+        0
+    };
+
+    let queue = if u32::from(stx.stx_mode) & libc::S_IFMT == libc::S_IFBLK {
+        sysfs_queue_dir(stx.stx_rdev_major, stx.stx_rdev_minor)
+    } else {
+        sysfs_queue_dir(stx.stx_dev_major, stx.stx_dev_minor)
+    };
+    let read_attr = |name: &str| -> Option<usize> {
+        let dir = queue.as_ref()?;
+        std::fs::read_to_string(dir.join(name))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+
+    let logical_block_size = read_attr("logical_block_size")
+        .or((dio_align > 0).then_some(dio_align))
+        .unwrap_or(512);
+    let physical_block_size = read_attr("physical_block_size")
+        .unwrap_or(fs_block_size)
+        .max(logical_block_size);
+    let optimal_io_size = read_attr("optimal_io_size").filter(|&size| size > 0);
+    let device = queue.as_ref().and_then(|dir| {
+        dir.parent()?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+
+    Ok(BlockGeometry {
+        logical_block_size,
+        physical_block_size,
+        optimal_io_size,
+        fs_block_size,
+        preferred_io_size: stx.stx_blksize as usize,
+        device,
+    })
+}
+
+/// sysfs `queue` directory for a device number, resolving partitions to
+/// their parent disk since only whole disks have a queue
+#[cfg(target_os = "linux")]
+pub(crate) fn sysfs_queue_dir(major: u32, minor: u32) -> Option<std::path::PathBuf> {
+    let dev = std::fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)).ok()?;
+    let disk = if dev.join("partition").exists() {
+        dev.parent()?.to_path_buf()
+    } else {
+        dev
+    };
+    let queue = disk.join("queue");
+    queue.is_dir().then_some(queue)
+}
+
+/// Detect the block geometry for the file (or directory) at `path`
+///
+/// macOS has no sysfs; the filesystem block size and `f_iosize` stand in for
+/// the device's block and optimal I/O sizes.
+#[cfg(target_os = "macos")]
+pub fn block_geometry(path: &Path) -> io::Result<BlockGeometry> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut sfs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut sfs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fs_block_size = sfs.f_bsize as usize;
+
+    Ok(BlockGeometry {
+        logical_block_size: fs_block_size,
+        physical_block_size: fs_block_size,
+        optimal_io_size: Some(sfs.f_iosize as usize).filter(|&size| size > 0),
+        fs_block_size,
+        preferred_io_size: std::fs::metadata(path)?.blksize() as usize,
+        device: None,
+    })
+}
@@ -0,0 +1,491 @@
+//! What a benchmark ran on: host, kernel, filesystem and block device.
+//!
+//! The same read costs very different amounts on an NVMe drive with `none`
+//! scheduling and a spinning disk behind `bfq`, and a kernel upgrade can move
+//! every number. A fingerprint of the environment goes into every result
+//! file so results are never compared without it. Everything is read from
+//! procfs and sysfs; what cannot be read is left as `None` rather than
+//! failing the run.
+
+use std::path::{Path, PathBuf};
+
+use crate::json::Json;
+
+/// Host-wide facts plus the storage behind each benchmarked path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    /// Kernel release, e.g. `6.8.0-45-generic`
+    pub kernel: Option<String>,
+    pub cpu_model: Option<String>,
+    pub cpus: usize,
+    /// One entry per distinct filesystem or device benchmarked
+    pub storage: Vec<Storage>,
+}
+
+/// The filesystem and block device behind one path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Storage {
+    /// First path seen on this filesystem
+    pub path: PathBuf,
+    /// Filesystem type, e.g. `ext4`; `None` for a block device node
+    pub fs_type: Option<String>,
+    pub mount_point: Option<PathBuf>,
+    /// What is mounted, e.g. `/dev/nvme0n1p2`
+    pub mount_source: Option<String>,
+    /// Per-mount and superblock options, e.g. `rw,relatime,discard`
+    pub mount_options: Option<String>,
+    /// Kernel name of the whole disk, e.g. `nvme0n1`
+    pub device: Option<String>,
+    pub model: Option<String>,
+    pub vendor: Option<String>,
+    pub rotational: Option<bool>,
+    /// Active I/O scheduler, e.g. `none` or `mq-deadline`
+    pub scheduler: Option<String>,
+    /// Requests the block layer queues per hardware queue (`queue/nr_requests`)
+    pub nr_requests: Option<u32>,
+    /// Commands the device accepts at once, where the driver reports it
+    /// (`device/queue_depth`, SCSI and SATA)
+    pub queue_depth: Option<u32>,
+    pub read_ahead_kb: Option<u32>,
+}
+
+/// Fingerprint the host and the storage behind `path`
+pub fn fingerprint(path: &Path) -> Environment {
+    let mut environment = Environment::host();
+    environment.add_path(path);
+    environment
+}
+
+impl Environment {
+    /// Host-wide facts only; add storage with `add_path`
+    pub fn host() -> Self {
+        Environment {
+            hostname: hostname(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            kernel: kernel_release(),
+            cpu_model: cpu_model(),
+            cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            storage: Vec::new(),
+        }
+    }
+
+    /// Record the storage behind `path`, unless its filesystem or device is
+    /// already recorded
+    pub fn add_path(&mut self, path: &Path) {
+        self.add_storage(storage(path));
+    }
+
+    fn add_storage(&mut self, storage: Storage) {
+        // Two paths whose mount and device are both unknown say nothing
+        // about whether they share storage
+        let identified = storage.mount_point.is_some() || storage.device.is_some();
+        let known = self.storage.iter().any(|s| {
+            s.path == storage.path
+                || (identified
+                    && s.mount_point == storage.mount_point
+                    && s.device == storage.device)
+        });
+        if !known {
+            self.storage.push(storage);
+        }
+    }
+
+    /// Distinct values of one storage field, joined with `;`
+    pub fn storage_summary(&self, field: impl Fn(&Storage) -> Option<String>) -> String {
+        let mut values: Vec<String> = Vec::new();
+        for value in self.storage.iter().filter_map(field) {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        values.join(";")
+    }
+
+    /// Read back an environment written by `to_json`; unknown or missing
+    /// fields are left empty
+    pub fn from_json(json: &Json) -> Self {
+        let string = |key: &str| json.get(key).and_then(Json::as_str).map(str::to_string);
+        Environment {
+            hostname: string("hostname").unwrap_or_default(),
+            os: string("os").unwrap_or_default(),
+            arch: string("arch").unwrap_or_default(),
+            kernel: string("kernel"),
+            cpu_model: string("cpu_model"),
+            cpus: json.get("cpus").and_then(Json::as_u64).unwrap_or(0) as usize,
+            storage: json
+                .get("storage")
+                .and_then(Json::as_array)
+                .unwrap_or_default()
+                .iter()
+                .map(Storage::from_json)
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("hostname", self.hostname.as_str().into()),
+            ("os", self.os.as_str().into()),
+            ("arch", self.arch.as_str().into()),
+            ("kernel", self.kernel.clone().into()),
+            ("cpu_model", self.cpu_model.clone().into()),
+            ("cpus", self.cpus.into()),
+            (
+                "storage",
+                Json::Array(self.storage.iter().map(Storage::to_json).collect()),
+            ),
+        ])
+    }
+}
+
+impl Storage {
+    fn from_json(json: &Json) -> Self {
+        let string = |key: &str| json.get(key).and_then(Json::as_str).map(str::to_string);
+        let number = |key: &str| json.get(key).and_then(Json::as_u64).map(|n| n as u32);
+        Storage {
+            path: string("path").map(PathBuf::from).unwrap_or_default(),
+            fs_type: string("fs_type"),
+            mount_point: string("mount_point").map(PathBuf::from),
+            mount_source: string("mount_source"),
+            mount_options: string("mount_options"),
+            device: string("device"),
+            model: string("model"),
+            vendor: string("vendor"),
+            rotational: json.get("rotational").and_then(Json::as_bool),
+            scheduler: string("scheduler"),
+            nr_requests: number("nr_requests"),
+            queue_depth: number("queue_depth"),
+            read_ahead_kb: number("read_ahead_kb"),
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let path = |p: &Path| Json::from(p.display().to_string());
+        Json::object([
+            ("path", path(&self.path)),
+            ("fs_type", self.fs_type.clone().into()),
+            (
+                "mount_point",
+                self.mount_point.as_deref().map_or(Json::Null, path),
+            ),
+            ("mount_source", self.mount_source.clone().into()),
+            ("mount_options", self.mount_options.clone().into()),
+            ("device", self.device.clone().into()),
+            ("model", self.model.clone().into()),
+            ("vendor", self.vendor.clone().into()),
+            ("rotational", self.rotational.into()),
+            ("scheduler", self.scheduler.clone().into()),
+            ("nr_requests", self.nr_requests.map(u64::from).into()),
+            ("queue_depth", self.queue_depth.map(u64::from).into()),
+            ("read_ahead_kb", self.read_ahead_kb.map(u64::from).into()),
+        ])
+    }
+}
+
+fn hostname() -> String {
+    let mut name = [0u8; 256];
+    let rc = unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) };
+    if rc != 0 {
+        return String::new();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+fn kernel_release() -> Option<String> {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return None;
+    }
+    let release = unsafe { std::ffi::CStr::from_ptr(uts.release.as_ptr()) };
+    Some(release.to_string_lossy().into_owned())
+}
+
+/// Trimmed contents of a sysfs or procfs file
+#[cfg(target_os = "linux")]
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    let s = std::fs::read_to_string(path).ok()?;
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_model() -> Option<String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        // "model name" on x86, "Model" on some ARM kernels
+        matches!(key.trim(), "model name" | "Model").then(|| value.trim().to_string())
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn cpu_model() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let mut len = buf.len();
+    let rc = unsafe {
+        libc::sysctlbyname(
+            c"machdep.cpu.brand_string".as_ptr(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if rc != 0 {
+        return None;
+    }
+    let end = buf[..len].iter().position(|&b| b == 0).unwrap_or(len);
+    Some(String::from_utf8_lossy(&buf[..end]).into_owned())
+}
+
+/// Mount and device facts for `path`, from `/proc/self/mountinfo` and sysfs
+#[cfg(target_os = "linux")]
+fn storage(path: &Path) -> Storage {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let mut storage = Storage {
+        path: path.to_path_buf(),
+        ..Storage::default()
+    };
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return storage;
+    };
+    let is_device = metadata.file_type().is_block_device();
+    let dev = if is_device {
+        metadata.rdev()
+    } else {
+        metadata.dev()
+    } as libc::dev_t;
+    let (major, minor) = (libc::major(dev) as u32, libc::minor(dev) as u32);
+
+    if !is_device {
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(mount) = find_mount(&canonical, major, minor) {
+            storage.fs_type = Some(mount.fs_type);
+            storage.mount_point = Some(mount.mount_point);
+            storage.mount_source = Some(mount.source);
+            storage.mount_options = Some(mount.options);
+        }
+    }
+
+    if let Some(queue) = crate::device::sysfs_queue_dir(major, minor) {
+        let disk = queue.parent().unwrap_or(&queue).to_path_buf();
+        let number = |name: &str| read_trimmed(queue.join(name))?.parse().ok();
+        storage.device = disk.file_name().map(|n| n.to_string_lossy().into_owned());
+        storage.model = read_trimmed(disk.join("device/model"));
+        storage.vendor = read_trimmed(disk.join("device/vendor"));
+        storage.rotational = read_trimmed(queue.join("rotational")).map(|r| r == "1");
+        // The active scheduler is the bracketed one, e.g. "none [mq-deadline] kyber"
+        storage.scheduler =
+            read_trimmed(queue.join("scheduler")).map(|s| match (s.find('['), s.find(']')) {
+                (Some(start), Some(end)) if start < end => s[start + 1..end].to_string(),
+                _ => s,
+            });
+        storage.nr_requests = number("nr_requests");
+        storage.read_ahead_kb = number("read_ahead_kb");
+        storage.queue_depth =
+            read_trimmed(disk.join("device/queue_depth")).and_then(|d| d.parse().ok());
+    }
+    storage
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+struct Mount {
+    mount_point: PathBuf,
+    fs_type: String,
+    source: String,
+    options: String,
+}
+
+/// The mount holding `path`: the longest mount point that prefixes it among
+/// mounts of device `major:minor`
+///
+/// `/proc/self/mountinfo` lines look like
+/// `28 1 254:0 / / rw,relatime - ext4 /dev/vda rw,discard`: mount options
+/// before the `-`, filesystem type, source and superblock options after.
+#[cfg(target_os = "linux")]
+fn find_mount(path: &Path, major: u32, minor: u32) -> Option<Mount> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    parse_mountinfo(&mountinfo, path, major, minor)
+}
+
+/// `find_mount` over the text of a mountinfo file
+#[cfg(target_os = "linux")]
+fn parse_mountinfo(mountinfo: &str, path: &Path, major: u32, minor: u32) -> Option<Mount> {
+    let device = format!("{}:{}", major, minor);
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            let mount: Vec<&str> = mount.split_whitespace().collect();
+            let fs: Vec<&str> = fs.split_whitespace().collect();
+            if mount.len() < 6 || fs.len() < 3 || mount[2] != device {
+                return None;
+            }
+            let mount_point = PathBuf::from(unescape_mount_path(mount[4]));
+            if !path.starts_with(&mount_point) {
+                return None;
+            }
+            // Per-mount options first, then the superblock's, without repeats
+            let mut options: Vec<&str> = mount[5].split(',').collect();
+            for option in fs[2].split(',') {
+                if !options.contains(&option) {
+                    options.push(option);
+                }
+            }
+            Some(Mount {
+                mount_point,
+                fs_type: fs[0].to_string(),
+                source: fs[1].to_string(),
+                options: options.join(","),
+            })
+        })
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+/// mountinfo escapes spaces, tabs, newlines and backslashes as octal
+#[cfg(target_os = "linux")]
+fn unescape_mount_path(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest
+            .get(i + 1..i + 4)
+            .and_then(|d| u8::from_str_radix(d, 8).ok());
+        match code {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// macOS has no sysfs; `statfs` still names the filesystem and its mount
+#[cfg(target_os = "macos")]
+fn storage(path: &Path) -> Storage {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut storage = Storage {
+        path: path.to_path_buf(),
+        ..Storage::default()
+    };
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return storage;
+    };
+    let mut sfs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut sfs) } != 0 {
+        return storage;
+    }
+    let text = |chars: &[libc::c_char]| unsafe {
+        CStr::from_ptr(chars.as_ptr())
+            .to_string_lossy()
+            .into_owned()
+    };
+    storage.fs_type = Some(text(&sfs.f_fstypename));
+    storage.mount_point = Some(PathBuf::from(text(&sfs.f_mntonname)));
+    storage.mount_source = Some(text(&sfs.f_mntfromname));
+    storage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_storage_dedups_known_storage() {
+        let mut environment = Environment::default();
+        let data = |path: &str| Storage {
+            path: PathBuf::from(path),
+            mount_point: Some(PathBuf::from("/data")),
+            device: Some("nvme0n1".to_string()),
+            ..Storage::default()
+        };
+        environment.add_storage(data("/data/a"));
+        environment.add_storage(data("/data/b"));
+        assert_eq!(environment.storage.len(), 1);
+
+        let mut root = data("/a");
+        root.mount_point = Some(PathBuf::from("/"));
+        environment.add_storage(root);
+        assert_eq!(environment.storage.len(), 2);
+
+        // Nothing known about either path: keep both, but not the same path twice
+        let unknown = |path: &str| Storage {
+            path: PathBuf::from(path),
+            ..Storage::default()
+        };
+        environment.add_storage(unknown("/missing/a"));
+        environment.add_storage(unknown("/missing/b"));
+        environment.add_storage(unknown("/missing/a"));
+        assert_eq!(environment.storage.len(), 4);
+        assert_eq!(environment.storage_summary(|s| s.device.clone()), "nvme0n1");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unescape() {
+        assert_eq!(unescape_mount_path("/mnt/plain"), "/mnt/plain");
+        assert_eq!(unescape_mount_path(r"/mnt/my\040disk"), "/mnt/my disk");
+        assert_eq!(unescape_mount_path(r"/a\011b\012c\134d"), "/a\tb\nc\\d");
+        // Not an escape: kept as is
+        assert_eq!(unescape_mount_path(r"/a\x"), r"/a\x");
+        assert_eq!(unescape_mount_path(r"/a\04"), r"/a\04");
+        assert_eq!(unescape_mount_path("/a\\"), "/a\\");
+    }
+
+    #[cfg(target_os = "linux")]
+    const MOUNTINFO: &str = r"22 28 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+28 1 254:0 / / rw,relatime shared:1 - ext4 /dev/vda rw,discard
+40 28 259:2 / /mnt/fast\040disk rw,noatime shared:20 - xfs /dev/nvme0n1p2 rw,attr2,noatime
+41 40 259:2 /sub /mnt/fast\040disk/sub rw,noatime - xfs /dev/nvme0n1p2 rw
+42 28 259:3 / /mnt/other rw - ext4 /dev/nvme1n1 rw
+bad line without a separator
+";
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mountinfo() {
+        let find =
+            |path: &str, major, minor| parse_mountinfo(MOUNTINFO, Path::new(path), major, minor);
+
+        assert_eq!(
+            find("/home/me/test.dat", 254, 0),
+            Some(Mount {
+                mount_point: PathBuf::from("/"),
+                fs_type: "ext4".to_string(),
+                source: "/dev/vda".to_string(),
+                options: "rw,relatime,discard".to_string(),
+            })
+        );
+
+        // The longest matching mount point wins, and options are not repeated
+        let fast = find("/mnt/fast disk/test.dat", 259, 2).unwrap();
+        assert_eq!(fast.mount_point, PathBuf::from("/mnt/fast disk"));
+        assert_eq!(fast.fs_type, "xfs");
+        assert_eq!(fast.options, "rw,noatime,attr2");
+        let sub = find("/mnt/fast disk/sub/test.dat", 259, 2).unwrap();
+        assert_eq!(sub.mount_point, PathBuf::from("/mnt/fast disk/sub"));
+
+        // Prefixes match whole components only
+        assert_eq!(find("/mnt/fast diskette/x", 259, 2), None);
+        // The device must match too
+        assert_eq!(find("/mnt/other/x", 259, 2), None);
+        assert_eq!(find("/mnt/other/x", 259, 3).unwrap().source, "/dev/nvme1n1");
+        assert_eq!(parse_mountinfo("", Path::new("/"), 254, 0), None);
+    }
+}
@@ -0,0 +1,356 @@
+//! Log-bucketed latency histogram in the style of HdrHistogram.
+//!
+//! Criterion reports means and confidence intervals, but disk latency is
+//! heavy-tailed and the tail is what a storage engine feels. Values are
+//! grouped by power of two, and each power of two is split into 128 linear
+//! sub-buckets, so any recorded value is reported within 1% of its true value
+//! while a histogram covering nanoseconds to seconds stays a few KB.
+
+use std::time::Duration;
+
+/// log2 of the number of linear sub-buckets per power of two
+const SUB_BITS: u32 = 7;
+const SUB_COUNT: u64 = 1 << SUB_BITS;
+
+/// Bucket index for `value`; values below `SUB_COUNT` get exact buckets
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_COUNT {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let shift = exp - SUB_BITS;
+    let mantissa = value >> shift;
+    ((shift as u64 + 1) * SUB_COUNT + (mantissa - SUB_COUNT)) as usize
+}
+
+/// Largest value that falls in bucket `index`
+fn bucket_upper(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_COUNT {
+        return index;
+    }
+    let shift = index / SUB_COUNT - 1;
+    let mantissa = index % SUB_COUNT + SUB_COUNT;
+    // `((mantissa + 1) << shift) - 1` overflows in the top power of two
+    (mantissa << shift) | ((1 << shift) - 1)
+}
+
+/// Histogram of `u64` samples, typically latencies in nanoseconds
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u128,
+    /// Sum of squared values, for the standard deviation
+    sum_squares: f64,
+    min: u64,
+    max: u64,
+}
+
+/// Standard latency percentiles, in the histogram's unit
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, value: u64) {
+        let index = bucket_index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        if self.total == 0 || value < self.min {
+            self.min = value;
+        }
+        self.max = self.max.max(value);
+        self.total += 1;
+        self.sum += value as u128;
+        self.sum_squares += (value as f64) * (value as f64);
+    }
+
+    /// Record a duration in nanoseconds
+    pub fn record_duration(&mut self, duration: Duration) {
+        self.record(duration.as_nanos().min(u64::MAX as u128) as u64);
+    }
+
+    /// Number of samples recorded
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Exact mean of the recorded values
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.total as f64
+    }
+
+    /// Sample standard deviation of the recorded values; 0 for fewer than two
+    pub fn stddev(&self) -> f64 {
+        if self.total < 2 {
+            return 0.0;
+        }
+        let n = self.total as f64;
+        let mean = self.mean();
+        ((self.sum_squares - n * mean * mean) / (n - 1.0))
+            .max(0.0)
+            .sqrt()
+    }
+
+    /// Sum of the recorded values
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    /// Smallest value at or below which `quantile` (0.0..=1.0) of samples fall
+    ///
+    /// Reported as the upper edge of the bucket, clamped to the exact maximum.
+    pub fn value_at_quantile(&self, quantile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_upper(index).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p50: self.value_at_quantile(0.50),
+            p90: self.value_at_quantile(0.90),
+            p99: self.value_at_quantile(0.99),
+            p999: self.value_at_quantile(0.999),
+            max: self.max,
+        }
+    }
+
+    /// Add another histogram's samples to this one
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.total == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (mine, theirs) in self.counts.iter_mut().zip(&other.counts) {
+            *mine += theirs;
+        }
+        if self.total == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.total += other.total;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Percentiles {
+    /// One-line summary treating values as nanoseconds, printed in microseconds
+    pub fn format_us(&self) -> String {
+        let us = |ns: u64| ns as f64 / 1e3;
+        format!(
+            "p50 {:.1}us  p90 {:.1}us  p99 {:.1}us  p99.9 {:.1}us  max {:.1}us",
+            us(self.p50),
+            us(self.p90),
+            us(self.p99),
+            us(self.p999),
+            us(self.max)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bucket bounds around every power of two
+    fn boundary_values() -> Vec<u64> {
+        let mut values = vec![0, u64::MAX];
+        for k in 0..64 {
+            let power = 1u64 << k;
+            values.extend([power - 1, power, power + 1]);
+        }
+        values
+    }
+
+    #[test]
+    fn small_values_are_exact() {
+        for value in 0..SUB_COUNT {
+            assert_eq!(bucket_index(value), value as usize);
+            assert_eq!(bucket_upper(value as usize), value);
+        }
+        // The first log bucket continues right after the exact ones
+        assert_eq!(bucket_index(SUB_COUNT), SUB_COUNT as usize);
+        assert_eq!(bucket_index(2 * SUB_COUNT), 2 * SUB_COUNT as usize);
+        assert_eq!(bucket_upper(2 * SUB_COUNT as usize), 2 * SUB_COUNT + 1);
+    }
+
+    #[test]
+    fn buckets_at_power_of_two_boundaries() {
+        for value in boundary_values() {
+            let index = bucket_index(value);
+            // The value's bucket holds it, and the one before ends below it
+            assert!(bucket_upper(index) >= value, "{}", value);
+            if index > 0 {
+                assert!(bucket_upper(index - 1) < value, "{}", value);
+            }
+            if value < u64::MAX {
+                assert!(bucket_index(value + 1) >= index, "{}", value);
+            }
+        }
+        for k in SUB_BITS..64 {
+            // A power of two starts a bucket; the value before it ends one
+            let power = 1u64 << k;
+            assert_eq!(bucket_index(power), bucket_index(power - 1) + 1);
+            assert_eq!(bucket_upper(bucket_index(power - 1)), power - 1);
+        }
+        assert_eq!(bucket_upper(bucket_index(u64::MAX)), u64::MAX);
+    }
+
+    #[test]
+    fn relative_error_within_one_percent() {
+        let mut values = boundary_values();
+        let mut value = 1u64;
+        while let Some(next) = value.checked_mul(3) {
+            values.extend([value, value + 7, next - 1]);
+            value = next;
+        }
+        for value in values {
+            let upper = bucket_upper(bucket_index(value));
+            assert!(upper >= value);
+            assert!(
+                (upper - value) as f64 <= value as f64 / SUB_COUNT as f64,
+                "{} reported as {}",
+                value,
+                upper
+            );
+        }
+    }
+
+    #[test]
+    fn quantiles_of_uniform_values() {
+        let mut histogram = Histogram::new();
+        for value in (1..=10_000).rev() {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.count(), 10_000);
+        assert_eq!((histogram.min(), histogram.max()), (1, 10_000));
+        assert_eq!(histogram.mean(), 5000.5);
+        // Sample standard deviation of 1..=n is sqrt(n (n + 1) / 12)
+        let expected = (10_000.0f64 * 10_001.0 / 12.0).sqrt();
+        assert!((histogram.stddev() - expected).abs() < 1e-6);
+
+        for (quantile, exact) in [(0.5, 5000), (0.9, 9000), (0.99, 9900), (0.999, 9990)] {
+            let value = histogram.value_at_quantile(quantile);
+            assert!(
+                value >= exact && value - exact <= exact / 100,
+                "q{} = {}",
+                quantile,
+                value
+            );
+        }
+        assert_eq!(histogram.value_at_quantile(0.0), 1);
+        assert_eq!(histogram.value_at_quantile(1.0), 10_000);
+        assert_eq!(histogram.percentiles().max, 10_000);
+    }
+
+    #[test]
+    fn quantiles_of_skewed_values() {
+        let mut histogram = Histogram::new();
+        for _ in 0..990 {
+            histogram.record(100);
+        }
+        for _ in 0..10 {
+            histogram.record(1_000_000);
+        }
+        let p = histogram.percentiles();
+        assert_eq!((p.p50, p.p90, p.p99), (100, 100, 100));
+        // The tail is reported at the bucket edge, clamped to the maximum
+        assert_eq!((p.p999, p.max), (1_000_000, 1_000_000));
+    }
+
+    #[test]
+    fn empty_histogram() {
+        let histogram = Histogram::new();
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.mean(), 0.0);
+        assert_eq!(histogram.stddev(), 0.0);
+        assert_eq!(histogram.percentiles(), Percentiles::default());
+    }
+
+    #[test]
+    fn merge_matches_recording_everything() {
+        let values: Vec<u64> = (0..5_000u64).map(|i| i * i % 1_000_003 + 50).collect();
+        let mut all = Histogram::new();
+        let (mut low, mut high) = (Histogram::new(), Histogram::new());
+        for &value in &values {
+            all.record(value);
+            if value < 300_000 {
+                low.record(value);
+            } else {
+                high.record(value);
+            }
+        }
+
+        let mut merged = Histogram::new();
+        merged.merge(&high);
+        merged.merge(&Histogram::new());
+        merged.merge(&low);
+        assert_eq!(merged.count(), all.count());
+        assert_eq!((merged.min(), merged.max()), (all.min(), all.max()));
+        assert_eq!(merged.sum(), all.sum());
+        assert_eq!(merged.percentiles(), all.percentiles());
+        assert!((merged.stddev() - all.stddev()).abs() < 1e-6 * all.stddev());
+        for quantile in [0.1, 0.25, 0.5, 0.75, 0.95] {
+            assert_eq!(
+                merged.value_at_quantile(quantile),
+                all.value_at_quantile(quantile)
+            );
+        }
+
+        let mut cleared = merged.clone();
+        cleared.clear();
+        assert!(cleared.is_empty());
+    }
+
+    #[test]
+    fn record_duration_in_nanoseconds() {
+        let mut histogram = Histogram::new();
+        histogram.record_duration(Duration::from_micros(3));
+        histogram.record_duration(Duration::MAX);
+        assert_eq!((histogram.min(), histogram.max()), (3000, u64::MAX));
+    }
+}
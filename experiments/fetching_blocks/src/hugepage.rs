@@ -0,0 +1,76 @@
+//! What huge-page support the system offers for file data.
+//!
+//! Two mechanisms can make the page cache hand out memory in 2MB units
+//! instead of 4KB pages: transparent huge pages on file mappings marked
+//! `MADV_HUGEPAGE` (where the filesystem supports large folios), and files on
+//! a `hugetlbfs` mount, which are always backed by preallocated huge pages.
+
+use std::path::PathBuf;
+
+/// Huge-page configuration, as far as it could be read
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HugePageInfo {
+    /// Size of a PMD-mapped huge page, usually 2MB
+    pub pmd_size: Option<usize>,
+    /// Active THP mode: `always`, `madvise` or `never`
+    pub thp_mode: Option<String>,
+    /// First `hugetlbfs` mount point found in `/proc/mounts`
+    pub hugetlbfs_mount: Option<PathBuf>,
+    /// Default-size huge pages free for `hugetlbfs`
+    pub free_huge_pages: u64,
+}
+
+impl HugePageInfo {
+    /// THP can back mappings that ask for it with `MADV_HUGEPAGE`
+    pub fn thp_available(&self) -> bool {
+        self.pmd_size.is_some() && matches!(self.thp_mode.as_deref(), Some("always" | "madvise"))
+    }
+
+    /// A `hugetlbfs` mount exists and has at least one free huge page
+    pub fn hugetlbfs_available(&self) -> bool {
+        self.hugetlbfs_mount.is_some() && self.free_huge_pages > 0
+    }
+}
+
+/// Read huge-page support from sysfs and procfs (Linux only)
+#[cfg(target_os = "linux")]
+pub fn huge_page_info() -> HugePageInfo {
+    let thp = "/sys/kernel/mm/transparent_hugepage";
+    let read = |path: &str| std::fs::read_to_string(path).ok();
+
+    let pmd_size = read(&format!("{}/hpage_pmd_size", thp)).and_then(|s| s.trim().parse().ok());
+    // The active mode is the bracketed one, e.g. "always [madvise] never"
+    let thp_mode = read(&format!("{}/enabled", thp)).and_then(|s| {
+        let start = s.find('[')? + 1;
+        let end = s[start..].find(']')? + start;
+        Some(s[start..end].to_string())
+    });
+    let hugetlbfs_mount = read("/proc/mounts").and_then(|mounts| {
+        mounts.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, dir, fstype) = (fields.next()?, fields.next()?, fields.next()?);
+            (fstype == "hugetlbfs").then(|| PathBuf::from(dir))
+        })
+    });
+    let free_huge_pages = read("/proc/meminfo")
+        .and_then(|meminfo| {
+            meminfo.lines().find_map(|line| {
+                line.strip_prefix("HugePages_Free:")
+                    .and_then(|n| n.trim().parse().ok())
+            })
+        })
+        .unwrap_or(0);
+
+    HugePageInfo {
+        pmd_size,
+        thp_mode,
+        hugetlbfs_mount,
+        free_huge_pages,
+    }
+}
+
+/// macOS has neither THP controls nor `hugetlbfs`
+#[cfg(not(target_os = "linux"))]
+pub fn huge_page_info() -> HugePageInfo {
+    HugePageInfo::default()
+}
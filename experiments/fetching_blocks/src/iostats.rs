@@ -0,0 +1,366 @@
+//! Kernel I/O counters for verifying what actually reached the device.
+//!
+//! Timing alone only suggests that a 2KB read fetched a whole 4KB block.
+//! `/proc/diskstats` counts the sectors the device transferred and
+//! `/proc/self/io` the bytes this process caused to be read from storage, so
+//! diffing snapshots taken around a benchmark shows the real fetch size.
+//! Both files are Linux-only; elsewhere the snapshots are empty.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// `/proc/diskstats` always counts in 512-byte sectors, whatever the device's block size
+pub const SECTOR_SIZE: u64 = 512;
+
+/// Read and write counters for one block device from `/proc/diskstats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskStats {
+    pub reads_completed: u64,
+    pub reads_merged: u64,
+    pub sectors_read: u64,
+    pub read_time_ms: u64,
+    pub writes_completed: u64,
+    pub writes_merged: u64,
+    pub sectors_written: u64,
+    pub write_time_ms: u64,
+}
+
+impl DiskStats {
+    /// Counters for the device numbered `major:minor`, if it is listed
+    pub fn read(major: u32, minor: u32) -> io::Result<Option<DiskStats>> {
+        let text = fs::read_to_string("/proc/diskstats")?;
+        Ok(DiskStats::parse(&text, major, minor))
+    }
+
+    /// The row for `major:minor` in the text of `/proc/diskstats`
+    fn parse(text: &str, major: u32, minor: u32) -> Option<DiskStats> {
+        text.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 11
+                || fields[0].parse::<u32>().ok()? != major
+                || fields[1].parse::<u32>().ok()? != minor
+            {
+                return None;
+            }
+            let n = |i: usize| fields[i].parse::<u64>().unwrap_or(0);
+            Some(DiskStats {
+                reads_completed: n(3),
+                reads_merged: n(4),
+                sectors_read: n(5),
+                read_time_ms: n(6),
+                writes_completed: n(7),
+                writes_merged: n(8),
+                sectors_written: n(9),
+                write_time_ms: n(10),
+            })
+        })
+    }
+
+    /// Counter increments from `self` to `later`
+    ///
+    /// A counter that went backwards, because it wrapped or the device was
+    /// removed and added again, counts as no increment rather than a huge one.
+    pub fn delta(&self, later: &DiskStats) -> DiskStats {
+        DiskStats {
+            reads_completed: later.reads_completed.saturating_sub(self.reads_completed),
+            reads_merged: later.reads_merged.saturating_sub(self.reads_merged),
+            sectors_read: later.sectors_read.saturating_sub(self.sectors_read),
+            read_time_ms: later.read_time_ms.saturating_sub(self.read_time_ms),
+            writes_completed: later.writes_completed.saturating_sub(self.writes_completed),
+            writes_merged: later.writes_merged.saturating_sub(self.writes_merged),
+            sectors_written: later.sectors_written.saturating_sub(self.sectors_written),
+            write_time_ms: later.write_time_ms.saturating_sub(self.write_time_ms),
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.sectors_read * SECTOR_SIZE
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.sectors_written * SECTOR_SIZE
+    }
+}
+
+/// This process's I/O accounting from `/proc/self/io`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessIo {
+    /// Bytes returned by read-like syscalls, cached or not
+    pub rchar: u64,
+    pub wchar: u64,
+    pub syscr: u64,
+    pub syscw: u64,
+    /// Bytes this process caused to be fetched from storage
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+impl ProcessIo {
+    pub fn read() -> io::Result<ProcessIo> {
+        let text = fs::read_to_string("/proc/self/io")?;
+        Ok(ProcessIo::parse(&text))
+    }
+
+    /// The text of `/proc/self/io`; missing or unreadable counters are 0
+    fn parse(text: &str) -> ProcessIo {
+        let mut stats = ProcessIo::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().parse().unwrap_or(0);
+            match key {
+                "rchar" => stats.rchar = value,
+                "wchar" => stats.wchar = value,
+                "syscr" => stats.syscr = value,
+                "syscw" => stats.syscw = value,
+                "read_bytes" => stats.read_bytes = value,
+                "write_bytes" => stats.write_bytes = value,
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Counter increments from `self` to `later`, as for `DiskStats::delta`
+    pub fn delta(&self, later: &ProcessIo) -> ProcessIo {
+        ProcessIo {
+            rchar: later.rchar.saturating_sub(self.rchar),
+            wchar: later.wchar.saturating_sub(self.wchar),
+            syscr: later.syscr.saturating_sub(self.syscr),
+            syscw: later.syscw.saturating_sub(self.syscw),
+            read_bytes: later.read_bytes.saturating_sub(self.read_bytes),
+            write_bytes: later.write_bytes.saturating_sub(self.write_bytes),
+        }
+    }
+}
+
+/// Device and process counters at one instant
+#[derive(Debug, Clone)]
+pub struct IoSnapshot {
+    /// `None` when the file's device has no `/proc/diskstats` row (e.g.
+    /// tmpfs, btrfs anonymous devices) or on non-Linux systems
+    pub disk: Option<DiskStats>,
+    pub process: Option<ProcessIo>,
+    pub taken: Instant,
+}
+
+/// Counter increments between two snapshots
+#[derive(Debug, Clone)]
+pub struct IoDelta {
+    pub disk: Option<DiskStats>,
+    pub process: Option<ProcessIo>,
+    pub elapsed: Duration,
+}
+
+impl IoSnapshot {
+    /// Snapshot the counters of the device holding `path` and of this process
+    ///
+    /// The device is matched by the file's `st_dev` (or `st_rdev` for a
+    /// block device node), so partitions and device-mapper volumes report
+    /// their own row rather than the whole disk.
+    pub fn take(path: &Path) -> io::Result<IoSnapshot> {
+        let metadata = fs::metadata(path)?;
+        let dev = if metadata.file_type().is_block_device() {
+            metadata.rdev()
+        } else {
+            metadata.dev()
+        } as libc::dev_t;
+        let (major, minor) = (libc::major(dev) as u32, libc::minor(dev) as u32);
+        Ok(IoSnapshot {
+            disk: DiskStats::read(major, minor).ok().flatten(),
+            process: ProcessIo::read().ok(),
+            taken: Instant::now(),
+        })
+    }
+
+    pub fn delta(&self, later: &IoSnapshot) -> IoDelta {
+        IoDelta {
+            disk: self.disk.zip(later.disk).map(|(a, b)| a.delta(&b)),
+            process: self.process.zip(later.process).map(|(a, b)| a.delta(&b)),
+            elapsed: later.taken.duration_since(self.taken),
+        }
+    }
+}
+
+impl IoDelta {
+    /// Device sectors read per logical read the benchmark issued
+    pub fn sectors_per_read(&self, logical_reads: u64) -> Option<f64> {
+        let disk = self.disk?;
+        (logical_reads > 0).then(|| disk.sectors_read as f64 / logical_reads as f64)
+    }
+
+    /// One-line report of what the device and process read for `logical_reads` reads
+    ///
+    /// The device counters include I/O from every process using the device,
+    /// so run on an otherwise idle disk for clean numbers.
+    pub fn describe(&self, logical_reads: u64) -> String {
+        let per_read = |bytes: u64| {
+            if logical_reads == 0 {
+                0.0
+            } else {
+                bytes as f64 / logical_reads as f64
+            }
+        };
+        let disk = match self.disk {
+            Some(disk) => format!(
+                "device: {} sectors in {} requests ({:.1} sectors, {:.0} bytes per read)",
+                disk.sectors_read,
+                disk.reads_completed,
+                self.sectors_per_read(logical_reads).unwrap_or(0.0),
+                per_read(disk.bytes_read())
+            ),
+            None => "device: no diskstats".to_string(),
+        };
+        let process = match self.process {
+            Some(process) => format!(
+                "process: {} bytes from storage ({:.0} per read)",
+                process.read_bytes,
+                per_read(process.read_bytes)
+            ),
+            None => "process: no /proc/self/io".to_string(),
+        };
+        format!("{} reads; {}; {}", logical_reads, disk, process)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Kernel 5.5+ rows have 20 fields; older ones 14, partitions once only 7
+    const DISKSTATS: &str = "\
+ 259       0 nvme0n1 1000 20 80000 300 500 10 4000 200 0 400 500 0 0 0 0 40 10
+ 259       1 nvme0n1p1 900 20 72000 290 400 10 3200 190 0 380 480 0 0 0 0 0 0
+   8      16 sdb 5 6 7 8
+ 253       0 dm-0 12 0 96 4 0 0 0 0 0 4 4
+";
+
+    #[test]
+    fn diskstats_rows() {
+        let disk = DiskStats::parse(DISKSTATS, 259, 0).unwrap();
+        assert_eq!(
+            disk,
+            DiskStats {
+                reads_completed: 1000,
+                reads_merged: 20,
+                sectors_read: 80000,
+                read_time_ms: 300,
+                writes_completed: 500,
+                writes_merged: 10,
+                sectors_written: 4000,
+                write_time_ms: 200,
+            }
+        );
+        assert_eq!(disk.bytes_read(), 80000 * 512);
+        assert_eq!(disk.bytes_written(), 4000 * 512);
+
+        // Partitions are matched by minor number, not by prefix
+        assert_eq!(
+            DiskStats::parse(DISKSTATS, 259, 1).unwrap().sectors_read,
+            72000
+        );
+        assert_eq!(
+            DiskStats::parse(DISKSTATS, 253, 0).unwrap().sectors_read,
+            96
+        );
+        // Too short to hold the read and write counters
+        assert_eq!(DiskStats::parse(DISKSTATS, 8, 16), None);
+        assert_eq!(DiskStats::parse(DISKSTATS, 259, 2), None);
+        assert_eq!(DiskStats::parse("", 259, 0), None);
+    }
+
+    #[test]
+    fn process_io() {
+        let io = ProcessIo::parse(
+            "rchar: 4096000\nwchar: 100\nsyscr: 1000\nsyscw: 1\n\
+             read_bytes: 2048000\nwrite_bytes: 0\ncancelled_write_bytes: 0\n",
+        );
+        assert_eq!(
+            io,
+            ProcessIo {
+                rchar: 4_096_000,
+                wchar: 100,
+                syscr: 1000,
+                syscw: 1,
+                read_bytes: 2_048_000,
+                write_bytes: 0,
+            }
+        );
+        // Garbage values and missing lines leave 0
+        let io = ProcessIo::parse("rchar: lots\nnot a counter\nsyscr: 7\n");
+        assert_eq!(io.rchar, 0);
+        assert_eq!(io.syscr, 7);
+        assert_eq!(io.read_bytes, 0);
+    }
+
+    #[test]
+    fn deltas() {
+        let before = DiskStats::parse(DISKSTATS, 259, 0).unwrap();
+        let after = DiskStats {
+            reads_completed: 1250,
+            sectors_read: 82000,
+            ..before
+        };
+        let delta = before.delta(&after);
+        assert_eq!(delta.reads_completed, 250);
+        assert_eq!(delta.sectors_read, 2000);
+        assert_eq!(delta.writes_completed, 0);
+
+        // A 32-bit counter that wrapped, and one reset by re-adding the device
+        let wrapped = DiskStats {
+            sectors_read: 100,
+            read_time_ms: 0,
+            ..after
+        };
+        let delta = after.delta(&wrapped);
+        assert_eq!(delta.sectors_read, 0);
+        assert_eq!(delta.read_time_ms, 0);
+        assert_eq!(delta.reads_completed, 0);
+
+        let a = ProcessIo {
+            read_bytes: u32::MAX as u64,
+            rchar: 10,
+            ..ProcessIo::default()
+        };
+        let b = ProcessIo {
+            read_bytes: 4096,
+            rchar: 30,
+            ..ProcessIo::default()
+        };
+        assert_eq!(a.delta(&b).read_bytes, 0);
+        assert_eq!(a.delta(&b).rchar, 20);
+    }
+
+    #[test]
+    fn snapshot_delta() {
+        let before = IoSnapshot {
+            disk: DiskStats::parse(DISKSTATS, 259, 0),
+            process: Some(ProcessIo::default()),
+            taken: Instant::now(),
+        };
+        let after = IoSnapshot {
+            disk: before.disk.map(|d| DiskStats {
+                sectors_read: d.sectors_read + 800,
+                reads_completed: d.reads_completed + 100,
+                ..d
+            }),
+            process: None,
+            taken: before.taken + Duration::from_millis(10),
+        };
+        let delta = before.delta(&after);
+        assert_eq!(delta.elapsed, Duration::from_millis(10));
+        assert_eq!(delta.disk.unwrap().sectors_read, 800);
+        assert_eq!(delta.process, None);
+        assert_eq!(delta.sectors_per_read(100), Some(8.0));
+        assert_eq!(delta.sectors_per_read(0), None);
+        assert_eq!(
+            delta.describe(100),
+            "100 reads; device: 800 sectors in 100 requests (8.0 sectors, 4096 bytes per read); \
+             process: no /proc/self/io"
+        );
+    }
+}
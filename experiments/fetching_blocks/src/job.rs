@@ -0,0 +1,343 @@
+//! Experiment definitions: one `Job` per command line or job-file section.
+//!
+//! A job describes a matrix of runs (read sizes × backends × queue depths ×
+//! cache modes) against one path. Command-line flags and job-file keys share
+//! the same names and parsers, so a job file is a saved command line:
+//!
+//! ```text
+//! # Defaults for every job below
+//! [global]
+//! path = /mnt/nvme/test.dat
+//! duration = 10s
+//!
+//! [subblock-direct]
+//! sizes = 1k,2k,4k,8k
+//! backend = pread,io_uring
+//! qd = 1,16
+//!
+//! [subblock-cold]
+//! sizes = 1k,4k
+//! cache = cold,warm
+//! ```
+//!
+//! Sections are job names; `[global]` sets defaults for the sections that
+//! follow it. `#` and `;` start comments at the start of a line or after
+//! whitespace, so a path like `/data/run#2` keeps its `#`.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use fetching_blocks::load::Limit;
+use fetching_blocks::workload::OffsetPattern;
+use fetching_blocks::{parse_size, BackendKind};
+
+/// Page-cache state a run starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// O_DIRECT / F_NOCACHE: reads never touch the page cache
+    Direct,
+    /// Buffered reads against whatever is cached already
+    Buffered,
+    /// Buffered reads after evicting the file from the page cache
+    Cold,
+    /// Buffered reads after reading the whole file into the page cache
+    Warm,
+}
+
+impl CacheMode {
+    pub const ALL: [CacheMode; 4] = [
+        CacheMode::Direct,
+        CacheMode::Buffered,
+        CacheMode::Cold,
+        CacheMode::Warm,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheMode::Direct => "direct",
+            CacheMode::Buffered => "buffered",
+            CacheMode::Cold => "cold",
+            CacheMode::Warm => "warm",
+        }
+    }
+}
+
+impl FromStr for CacheMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        CacheMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| format!("unknown cache mode '{}'", s))
+    }
+}
+
+/// One experiment: every combination of its lists, against one path
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    /// `None` until set; a job cannot run without one
+    pub path: Option<PathBuf>,
+    /// Empty means "derive from the block geometry"
+    pub sizes: Vec<usize>,
+    pub pattern: OffsetPattern,
+    pub backends: Vec<BackendKind>,
+    pub queue_depths: Vec<usize>,
+    pub limit: Limit,
+    pub caches: Vec<CacheMode>,
+    /// `None` means "the device's fetch unit"
+    pub block_size: Option<u64>,
+    pub seed: u64,
+}
+
+impl Job {
+    /// Option names accepted by `set`
+    pub const KEYS: [&'static str; 10] = [
+        "path",
+        "sizes",
+        "pattern",
+        "backend",
+        "qd",
+        "duration",
+        "reads",
+        "cache",
+        "block-size",
+        "seed",
+    ];
+
+    pub fn new(name: &str) -> Self {
+        Job {
+            name: name.to_string(),
+            path: None,
+            sizes: Vec::new(),
+            pattern: OffsetPattern::Uniform,
+            backends: vec![BackendKind::Pread],
+            queue_depths: vec![1],
+            limit: Limit::Duration(Duration::from_secs(5)),
+            caches: vec![CacheMode::Direct],
+            block_size: None,
+            seed: 42,
+        }
+    }
+
+    /// Set one option by its command-line / job-file name
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "path" => self.path = Some(PathBuf::from(value)),
+            "sizes" => self.sizes = parse_list(value, parse_size_arg)?,
+            "pattern" => self.pattern = value.parse()?,
+            "backend" => self.backends = parse_list(value, str::parse)?,
+            "qd" => self.queue_depths = parse_list(value, parse_queue_depth)?,
+            "duration" => self.limit = Limit::Duration(parse_duration(value)?),
+            "reads" => self.limit = Limit::Reads(parse_number(value)?),
+            "cache" => self.caches = parse_list(value, str::parse)?,
+            "block-size" => self.block_size = Some(parse_size_arg(value)? as u64),
+            "seed" => self.seed = parse_number(value)?,
+            _ => return Err(format!("unknown option '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parse a job file into its jobs, in file order
+pub fn parse_job_file(text: &str) -> Result<Vec<Job>, String> {
+    let mut global = Job::new("global");
+    let mut jobs: Vec<Job> = Vec::new();
+    // Index into `jobs` of the section being filled, `None` for `[global]`
+    let mut current: Option<usize> = None;
+    let mut in_section = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let at = |e: String| format!("line {}: {}", number + 1, e);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim();
+            in_section = true;
+            if name == "global" {
+                current = None;
+                continue;
+            }
+            if name.is_empty() || name.contains('/') {
+                return Err(at(format!("invalid job name '{}'", name)));
+            }
+            if jobs.iter().any(|job| job.name == name) {
+                return Err(at(format!("duplicate job '{}'", name)));
+            }
+            let mut job = global.clone();
+            job.name = name.to_string();
+            jobs.push(job);
+            current = Some(jobs.len() - 1);
+            continue;
+        }
+
+        if !in_section {
+            return Err(at("option outside a [section]".to_string()));
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| at(format!("expected 'key = value', got '{}'", line)))?;
+        let job = match current {
+            Some(index) => &mut jobs[index],
+            None => &mut global,
+        };
+        job.set(key.trim(), value.trim()).map_err(at)?;
+    }
+
+    if jobs.is_empty() {
+        return Err("no jobs defined".to_string());
+    }
+    if let Some(job) = jobs.iter().find(|job| job.path.is_none()) {
+        return Err(format!("job '{}' has no path", job.name));
+    }
+    Ok(jobs)
+}
+
+/// `line` up to the first `#` or `;` that starts it or follows whitespace
+fn strip_comment(line: &str) -> &str {
+    let mut after_space = true;
+    for (i, c) in line.char_indices() {
+        if after_space && (c == '#' || c == ';') {
+            return &line[..i];
+        }
+        after_space = c.is_whitespace();
+    }
+    line
+}
+
+/// Comma-separated values, each parsed with `parse`
+fn parse_list<T>(s: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<Vec<T>, String> {
+    s.split(',').map(|item| parse(item.trim())).collect()
+}
+
+fn parse_size_arg(s: &str) -> Result<usize, String> {
+    parse_size(s)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("invalid size '{}'", s))
+}
+
+fn parse_queue_depth(s: &str) -> Result<usize, String> {
+    let qd: usize = parse_number(s)?;
+    if qd == 0 {
+        return Err("queue depth must be at least 1".to_string());
+    }
+    Ok(qd)
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number '{}'", s))
+}
+
+/// `250ms`, `10s`, `1.5s`, `2m`, or plain seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}'", s);
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1e3,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments() {
+        assert_eq!(strip_comment("# all comment"), "");
+        assert_eq!(strip_comment("  ; indented"), "  ");
+        assert_eq!(strip_comment("qd = 4 # deep"), "qd = 4 ");
+        assert_eq!(strip_comment("qd = 4\t;deep"), "qd = 4\t");
+        assert_eq!(strip_comment("path = /data/run#2"), "path = /data/run#2");
+        assert_eq!(strip_comment("path = /a;b # c"), "path = /a;b ");
+    }
+
+    #[test]
+    fn global_defaults() {
+        let jobs = parse_job_file(
+            "# Defaults
+[global]
+path = /data/run#2
+duration = 10s
+
+[small]
+sizes = 1k, 2k
+qd = 1,16 ; two depths
+
+[global]
+seed = 7
+
+[cold]
+path = /dev/nvme0n1
+cache = cold,warm
+reads = 1000
+",
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 2);
+
+        let small = &jobs[0];
+        assert_eq!(small.name, "small");
+        assert_eq!(small.path, Some(PathBuf::from("/data/run#2")));
+        assert_eq!(small.sizes, [1024, 2048]);
+        assert_eq!(small.queue_depths, [1, 16]);
+        assert_eq!(small.limit, Limit::Duration(Duration::from_secs(10)));
+        // Set in a later [global], after this job was defined
+        assert_eq!(small.seed, Job::new("").seed);
+
+        let cold = &jobs[1];
+        assert_eq!(cold.path, Some(PathBuf::from("/dev/nvme0n1")));
+        assert_eq!(cold.caches, [CacheMode::Cold, CacheMode::Warm]);
+        assert_eq!(cold.limit, Limit::Reads(1000));
+        assert_eq!(cold.seed, 7);
+        assert_eq!(cold.sizes, Vec::<usize>::new());
+    }
+
+    #[test]
+    fn job_file_errors() {
+        let error = |text: &str| parse_job_file(text).unwrap_err();
+        assert_eq!(error(""), "no jobs defined");
+        assert_eq!(error("qd = 1"), "line 1: option outside a [section]");
+        assert_eq!(error("[a]\npath = x\n[a]"), "line 3: duplicate job 'a'");
+        assert_eq!(error("[a/b]"), "line 1: invalid job name 'a/b'");
+        assert_eq!(error("[]"), "line 1: invalid job name ''");
+        assert_eq!(error("[a]\nqd"), "line 2: expected 'key = value', got 'qd'");
+        assert_eq!(
+            error("[a]\nqd = 0"),
+            "line 2: queue depth must be at least 1"
+        );
+        assert_eq!(
+            error("[a]\ncolour = red"),
+            "line 2: unknown option 'colour'"
+        );
+        assert_eq!(error("[a]\nqd = 1"), "job 'a' has no path");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+        for bad in ["", "s", "10h", "1..5s", "-1s", "ms10"] {
+            assert_eq!(
+                parse_duration(bad),
+                Err(format!("invalid duration '{}'", bad))
+            );
+        }
+    }
+}
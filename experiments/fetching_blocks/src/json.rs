@@ -0,0 +1,609 @@
+//! Minimal JSON values for result files.
+//!
+//! Results are a few flat objects of numbers and strings; a hand-rolled
+//! writer and parser keep the crate free of serialization dependencies.
+//! Objects keep their keys in insertion order so files diff cleanly between
+//! runs. JSON has no NaN or infinity, so non-finite numbers are written as
+//! `null` and read back as `Json::Null`, not as numbers.
+
+use std::fmt::{self, Write};
+
+/// Deepest nesting of arrays and objects `Json::parse` accepts; result files
+/// need a handful of levels, and the parser recurses once per level
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Written as `null` when not finite
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object from `(key, value)` pairs, in order
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Parse a complete JSON document
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Value of `key` if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64().filter(|n| *n >= 0.0).map(|n| n as u64)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn write_indented(&self, out: &mut impl Write, indent: usize) -> fmt::Result {
+        let pad = |out: &mut dyn Write, depth: usize| write!(out, "{:1$}", "", depth * 2);
+        match self {
+            Json::Null => out.write_str("null"),
+            Json::Bool(b) => write!(out, "{}", b),
+            Json::Number(n) => write_number(out, *n),
+            Json::String(s) => write_string(out, s),
+            Json::Array(items) if items.is_empty() => out.write_str("[]"),
+            Json::Array(items) => {
+                out.write_str("[\n")?;
+                for (i, item) in items.iter().enumerate() {
+                    pad(out, indent + 1)?;
+                    item.write_indented(out, indent + 1)?;
+                    out.write_str(if i + 1 < items.len() { ",\n" } else { "\n" })?;
+                }
+                pad(out, indent)?;
+                out.write_str("]")
+            }
+            Json::Object(fields) if fields.is_empty() => out.write_str("{}"),
+            Json::Object(fields) => {
+                out.write_str("{\n")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    pad(out, indent + 1)?;
+                    write_string(out, key)?;
+                    out.write_str(": ")?;
+                    value.write_indented(out, indent + 1)?;
+                    out.write_str(if i + 1 < fields.len() { ",\n" } else { "\n" })?;
+                }
+                pad(out, indent)?;
+                out.write_str("}")
+            }
+        }
+    }
+}
+
+/// Pretty-printed with two-space indentation
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+/// Integers print without a fraction; NaN and infinities, which JSON lacks, as `null`
+fn write_number(out: &mut impl Write, n: f64) -> fmt::Result {
+    if !n.is_finite() {
+        out.write_str("null")
+    } else if n.fract() == 0.0 && n.abs() < 1e15 {
+        write!(out, "{}", n as i64)
+    } else {
+        write!(out, "{}", n)
+    }
+}
+
+fn write_string(out: &mut impl Write, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Whether `text` matches JSON's number grammar:
+/// `-? (0 | [1-9][0-9]*) (. [0-9]+)? ([eE] [+-]? [0-9]+)?`
+fn is_json_number(text: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = text.strip_prefix('-').unwrap_or(text);
+    let int = digits(rest);
+    if int == 0 || (int > 1 && rest.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &rest[int..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let n = digits(fraction);
+        if n == 0 {
+            return false;
+        }
+        rest = &fraction[n..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let n = digits(exponent);
+        if n == 0 {
+            return false;
+        }
+        rest = &exponent[n..];
+    }
+    rest.is_empty()
+}
+
+/// Recursive-descent parser over the raw bytes; strings are re-validated as
+/// UTF-8 since the input is a `&str` and escapes only add valid characters
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Arrays and objects open at `pos`
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("invalid JSON at byte {}: {}", self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.bytes.get(self.pos) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected character"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(&open @ (b'[' | b'{')) => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("nesting too deep"));
+                }
+                self.depth += 1;
+                let value = if open == b'[' {
+                    self.array()
+                } else {
+                    self.object()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+        let invalid = || self.error(&format!("invalid number '{}'", text));
+        // Rust accepts forms JSON does not, such as `01`, `1.` and `.5`
+        if !is_json_number(text) {
+            return Err(invalid());
+        }
+        let n: f64 = text.parse().map_err(|_| invalid())?;
+        if !n.is_finite() {
+            return Err(self.error(&format!("number '{}' out of range", text)));
+        }
+        Ok(Json::Number(n))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while !matches!(self.bytes.get(self.pos), None | Some(b'"' | b'\\')) {
+                if self.bytes[self.pos] < 0x20 {
+                    return Err(self.error("control character in string"));
+                }
+                self.pos += 1;
+            }
+            out.push_str(
+                std::str::from_utf8(&self.bytes[start..self.pos])
+                    .map_err(|_| self.error("invalid UTF-8"))?,
+            );
+            match self.bytes.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                _ => {
+                    self.pos += 1;
+                    let escape = self.bytes.get(self.pos).copied();
+                    self.pos += 1;
+                    match escape {
+                        Some(b'"') => out.push('"'),
+                        Some(b'\\') => out.push('\\'),
+                        Some(b'/') => out.push('/'),
+                        Some(b'b') => out.push('\u{8}'),
+                        Some(b'f') => out.push('\u{c}'),
+                        Some(b'n') => out.push('\n'),
+                        Some(b'r') => out.push('\r'),
+                        Some(b't') => out.push('\t'),
+                        Some(b'u') => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, joining surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.bytes[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: &Json) {
+        let text = value.to_string();
+        assert_eq!(&Json::parse(&text).unwrap(), value, "{}", text);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(&Json::Null);
+        round_trip(&Json::Array(Vec::new()));
+        round_trip(&Json::object::<&str>([]));
+        round_trip(&Json::object([
+            (
+                "name",
+                Json::from("quote \" backslash \\ slash / tab \t nl \n cr \r"),
+            ),
+            ("control", Json::from("\u{1}\u{8}\u{c}\u{1f}")),
+            ("unicode", Json::from("é ✓ 𝄞 😀")),
+            ("empty", Json::from("")),
+            (
+                "numbers",
+                Json::Array(
+                    [
+                        0.0,
+                        -0.5,
+                        42.0,
+                        -7.0,
+                        1e15,
+                        1e300,
+                        1.5e-9,
+                        0.1 + 0.2,
+                        f64::MAX,
+                    ]
+                    .map(Json::from)
+                    .to_vec(),
+                ),
+            ),
+            (
+                "flags",
+                Json::Array(vec![true.into(), false.into(), Json::Null]),
+            ),
+            (
+                "nested",
+                Json::object([(
+                    "a",
+                    Json::Array(vec![Json::object([(
+                        "b",
+                        Json::Array(vec![Json::from(1u64)]),
+                    )])]),
+                )]),
+            ),
+        ]));
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let parse = |text: &str| Json::parse(text).unwrap();
+        assert_eq!(parse(r#""a\"b\\c\/d""#), Json::from("a\"b\\c/d"));
+        assert_eq!(parse(r#""\b\f\n\r\t""#), Json::from("\u{8}\u{c}\n\r\t"));
+        assert_eq!(parse(r#""\u0041\u00e9\u2713""#), Json::from("Aé✓"));
+        // Surrogate pairs, in either case
+        assert_eq!(parse(r#""\ud834\udd1e""#), Json::from("𝄞"));
+        assert_eq!(parse(r#""\uD83D\uDE00!""#), Json::from("😀!"));
+    }
+
+    #[test]
+    fn parses_numbers() {
+        let parse = |text: &str| Json::parse(text).unwrap().as_f64().unwrap();
+        assert_eq!(parse("0"), 0.0);
+        assert_eq!(parse("-0.25"), -0.25);
+        assert_eq!(parse("1e3"), 1000.0);
+        assert_eq!(parse("1E+3"), 1000.0);
+        assert_eq!(parse("25e-1"), 2.5);
+        assert_eq!(parse("-1.5e-3"), -0.0015);
+        assert_eq!(parse(" 12 "), 12.0);
+    }
+
+    #[test]
+    fn parses_whitespace_and_nesting() {
+        let value =
+            Json::parse(" {\n\t\"a\" : [ 1 , { \"b\" : null } ] ,\r\n \"c\":true } ").unwrap();
+        assert_eq!(
+            value,
+            Json::object([
+                (
+                    "a",
+                    Json::Array(vec![Json::from(1u64), Json::object([("b", Json::Null)])])
+                ),
+                ("c", Json::from(true)),
+            ])
+        );
+        assert_eq!(value.get("c").and_then(Json::as_bool), Some(true));
+    }
+
+    #[test]
+    fn writes_indented() {
+        let value = Json::object([
+            ("name", Json::from("a")),
+            ("sizes", Json::Array(vec![512u64.into(), 4096u64.into()])),
+            ("empty", Json::Array(Vec::new())),
+            ("none", Json::from(None::<u64>)),
+            ("nested", Json::object([("ok", Json::from(true))])),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            "{\n  \"name\": \"a\",\n  \"sizes\": [\n    512,\n    4096\n  ],\n  \
+             \"empty\": [],\n  \"none\": null,\n  \"nested\": {\n    \"ok\": true\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn writes_escapes() {
+        assert_eq!(
+            Json::from("quote \" backslash \\ tab \t nl \n cr \r é").to_string(),
+            r#""quote \" backslash \\ tab \t nl \n cr \r é""#
+        );
+        assert_eq!(Json::from("\u{1}\u{1f}").to_string(), r#""\u0001\u001f""#);
+    }
+
+    #[test]
+    fn writes_non_finite_as_null() {
+        assert_eq!(Json::from(f64::NAN).to_string(), "null");
+        assert_eq!(Json::from(f64::INFINITY).to_string(), "null");
+        assert_eq!(Json::from(3.0).to_string(), "3");
+        assert_eq!(Json::from(-0.25).to_string(), "-0.25");
+        assert_eq!(Json::from(1e15).to_string(), "1000000000000000");
+        let object = Json::object([("iops", Json::from(f64::NEG_INFINITY))]);
+        let read = Json::parse(&object.to_string()).unwrap();
+        assert_eq!(read.get("iops"), Some(&Json::Null));
+        assert_eq!(read.get("iops").and_then(Json::as_f64), None);
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for text in [
+            "",
+            "   ",
+            "nul",
+            "True",
+            "[1,]",
+            "[1 2]",
+            "{\"a\" 1}",
+            "{\"a\": 1,}",
+            "{a: 1}",
+            "[",
+            "{\"a\": [}",
+            "\"unterminated",
+            "\"bad \\x escape\"",
+            "\"raw\ttab\"",
+            "\"\\u12\"",
+            "\"\\ud800\"",
+            "\"\\ud800\\u0041\"",
+            "\"\\udc00\"",
+            "01",
+            "1.",
+            ".5",
+            "-",
+            "+1",
+            "1e",
+            "1e+",
+            "--1",
+            "1e400",
+            "1 2",
+            "{} x",
+        ] {
+            assert!(Json::parse(text).is_err(), "accepted {:?}", text);
+        }
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        let err = Json::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert!(err.contains("nesting too deep"), "{}", err);
+        // Far deeper than the stack could take
+        assert!(Json::parse(&"[".repeat(1_000_000)).is_err());
+        assert!(Json::parse(&"{\"a\":".repeat(1_000_000)).is_err());
+    }
+}
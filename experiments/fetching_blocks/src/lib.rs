@@ -1 +1,66 @@
-// Empty lib.rs - benchmarks are in benches/
+//! Helpers shared by the `fetching_blocks` benchmarks and binary.
+
+pub mod backend;
+pub mod buf;
+pub mod cache;
+pub mod chart;
+pub mod device;
+pub mod environment;
+pub mod histogram;
+pub mod hugepage;
+pub mod iostats;
+pub mod json;
+pub mod load;
+pub mod mapping;
+pub mod nocache;
+pub mod readahead;
+pub mod report;
+pub mod residency;
+pub mod sst;
+pub mod workload;
+
+pub use backend::{BackendKind, IoBackend};
+pub use buf::{AlignedBuf, BufPool, PooledBuf};
+pub use cache::{BlockCache, PolicyKind};
+pub use chart::Chart;
+pub use device::{block_geometry, BlockGeometry};
+pub use environment::{fingerprint, Environment};
+pub use histogram::{Histogram, Percentiles};
+pub use iostats::{IoDelta, IoSnapshot};
+pub use report::{Measurement, Report};
+pub use residency::{residency, Residency};
+
+/// Size in bytes of a regular file or block device
+///
+/// `metadata().len()` is 0 for block devices; seeking to the end works for both.
+pub fn file_size(file: &std::fs::File) -> std::io::Result<u64> {
+    use std::io::{Seek, SeekFrom};
+    let mut file = file;
+    file.seek(SeekFrom::End(0))
+}
+
+/// Parse a byte count like `512`, `4k`, `4KB` or `2M` (powers of 1024)
+pub fn parse_size(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let shift = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return None,
+    };
+    number.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+/// Human-readable byte count for benchmark IDs and reports, e.g. `512B`, `3KB`, `2MB`
+pub fn format_size(bytes: usize) -> String {
+    if bytes >= 1 << 20 && bytes.is_multiple_of(1 << 20) {
+        format!("{}MB", bytes >> 20)
+    } else if bytes >= 1 << 10 && bytes.is_multiple_of(1 << 10) {
+        format!("{}KB", bytes >> 10)
+    } else {
+        format!("{}B", bytes)
+    }
+}
@@ -0,0 +1,397 @@
+//! Concurrent random-read load at a fixed queue depth.
+//!
+//! A storage engine never waits for one read before issuing the next, so
+//! serial latency says little about what it will see. `run_reads` keeps
+//! `queue_depth` reads in flight, either with one thread per outstanding
+//! read or with a single `io_uring` instance, and reports throughput and
+//! latency.
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Barrier, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::backend::BackendKind;
+use crate::buf::{aligned_span, AlignedBuf};
+use crate::file_size;
+use crate::histogram::Histogram;
+use crate::nocache::{dio_alignment, open_nocache};
+use crate::workload::{OffsetGen, OffsetPattern};
+
+/// Buffers are page-aligned, which satisfies any direct I/O alignment
+const BUF_ALIGN: usize = 4096;
+
+/// How outstanding reads are kept in flight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// `queue_depth` threads, each issuing blocking reads through the backend
+    Threads(BackendKind),
+    /// One thread keeping `queue_depth` reads queued on an `io_uring`
+    IoUring,
+}
+
+impl Engine {
+    pub fn name(&self) -> String {
+        match self {
+            Engine::Threads(kind) => format!("threads_{}", kind.name()),
+            Engine::IoUring => "io_uring_async".to_string(),
+        }
+    }
+}
+
+/// When a load run stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// After this many reads in total, across all workers
+    Reads(u64),
+    /// After this much wall-clock time
+    Duration(Duration),
+}
+
+/// Shape of a load run
+#[derive(Debug, Clone, Copy)]
+pub struct LoadSpec {
+    pub read_size: usize,
+    pub queue_depth: usize,
+    pub pattern: OffsetPattern,
+    /// Offsets are multiples of this
+    pub block_size: u64,
+    /// Bypass the page cache
+    pub direct: bool,
+    pub seed: u64,
+}
+
+/// Totals from one or more load runs
+#[derive(Debug, Clone, Default)]
+pub struct LoadStats {
+    pub reads: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Per-read latency in nanoseconds
+    pub latency: Histogram,
+}
+
+impl LoadStats {
+    /// Reads completed per second, 0 for a run that did nothing
+    pub fn iops(&self) -> f64 {
+        self.per_second(self.reads)
+    }
+
+    /// Bytes requested per second, 0 for a run that did nothing
+    pub fn bandwidth(&self) -> f64 {
+        self.per_second(self.bytes)
+    }
+
+    fn per_second(&self, count: u64) -> f64 {
+        if self.reads == 0 || self.elapsed.is_zero() {
+            return 0.0;
+        }
+        count as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn mean_latency(&self) -> Duration {
+        Duration::from_nanos(self.latency.mean() as u64)
+    }
+
+    pub fn max_latency(&self) -> Duration {
+        Duration::from_nanos(self.latency.max())
+    }
+
+    /// Fold another run's totals into this one
+    pub fn merge(&mut self, other: &LoadStats) {
+        self.reads += other.reads;
+        self.bytes += other.bytes;
+        self.elapsed += other.elapsed;
+        self.latency.merge(&other.latency);
+    }
+
+    fn record(&mut self, bytes: usize, latency: Duration) {
+        self.reads += 1;
+        self.bytes += bytes as u64;
+        self.latency.record_duration(latency);
+    }
+}
+
+/// Issue random reads against `path` at `spec.queue_depth` until `limit`
+///
+/// `path` may be a regular file or a block device. Offsets are drawn from
+/// `spec.pattern` over the whole file, less one read
+/// so the last read never runs off the end. Each read is widened to the
+/// direct I/O alignment when `spec.direct` is set.
+pub fn run_reads(
+    path: &Path,
+    spec: &LoadSpec,
+    engine: Engine,
+    limit: Limit,
+) -> io::Result<LoadStats> {
+    let file_size = file_size(&std::fs::File::open(path)?)?;
+    let align = if spec.direct {
+        dio_alignment(&open_nocache(path)?)
+    } else {
+        1
+    };
+    let span = file_size.saturating_sub(spec.read_size as u64 + align as u64);
+    let offsets = |worker: u64| {
+        OffsetGen::new(
+            spec.pattern,
+            span,
+            spec.block_size,
+            spec.seed.wrapping_add(worker),
+        )
+    };
+    let job = Job {
+        path,
+        spec,
+        align,
+        limit,
+    };
+
+    match engine {
+        Engine::Threads(kind) => run_threads(&job, kind, offsets),
+        Engine::IoUring => run_uring(&job, offsets(0)),
+    }
+}
+
+struct Job<'a> {
+    path: &'a Path,
+    spec: &'a LoadSpec,
+    align: usize,
+    limit: Limit,
+}
+
+/// Tracks whether the run's limit has been reached, shared by all workers
+struct Budget {
+    remaining: AtomicU64,
+    duration: Option<Duration>,
+    start: OnceLock<Instant>,
+    stop: AtomicBool,
+}
+
+impl Budget {
+    fn new(limit: Limit) -> Self {
+        let (remaining, duration) = match limit {
+            Limit::Reads(n) => (n, None),
+            Limit::Duration(d) => (u64::MAX, Some(d)),
+        };
+        Budget {
+            remaining: AtomicU64::new(remaining),
+            duration,
+            start: OnceLock::new(),
+            stop: AtomicBool::new(false),
+        }
+    }
+
+    /// When the run started; the first call starts the clock
+    fn start(&self) -> Instant {
+        *self.start.get_or_init(Instant::now)
+    }
+
+    /// Claim permission for one more read
+    fn take(&self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        if let Some(duration) = self.duration {
+            if self.start().elapsed() >= duration {
+                self.stop.store(true, Ordering::Relaxed);
+                return false;
+            }
+        }
+        self.remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+fn run_threads(
+    job: &Job<'_>,
+    kind: BackendKind,
+    offsets: impl Fn(u64) -> OffsetGen + Sync,
+) -> io::Result<LoadStats> {
+    let depth = job.spec.queue_depth.max(1);
+    let mut backends = Vec::with_capacity(depth);
+    for _ in 0..depth {
+        let mut backend = kind.create()?;
+        backend.open(job.path, job.spec.direct)?;
+        backends.push(backend);
+    }
+
+    let budget = Budget::new(job.limit);
+    let barrier = Barrier::new(depth + 1);
+    let totals = Mutex::new(LoadStats::default());
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = backends
+            .into_iter()
+            .enumerate()
+            .map(|(worker, mut backend)| {
+                let (budget, barrier, totals, offsets) = (&budget, &barrier, &totals, &offsets);
+                scope.spawn(move || -> io::Result<()> {
+                    let mut offsets = offsets(worker as u64);
+                    let buf_len = aligned_span(0, job.spec.read_size, job.align).1 + job.align;
+                    let mut buf = AlignedBuf::new(buf_len, BUF_ALIGN);
+                    let mut stats = LoadStats::default();
+
+                    // Everyone starts together so the clock covers only the reads
+                    barrier.wait();
+                    while budget.take() {
+                        let (offset, len) =
+                            aligned_span(offsets.next_offset(), job.spec.read_size, job.align);
+                        let start = Instant::now();
+                        let result = backend.read_at(&mut buf[..len], offset);
+                        if let Err(e) = result.and_then(|n| check_full(n, len)) {
+                            budget.stop.store(true, Ordering::Relaxed);
+                            return Err(e);
+                        }
+                        stats.record(job.spec.read_size, start.elapsed());
+                    }
+                    backend.close()?;
+                    totals.lock().unwrap().merge(&stats);
+                    Ok(())
+                })
+            })
+            .collect();
+
+        barrier.wait();
+        let start = budget.start();
+        // Join every worker before reporting the first error
+        let mut result = Ok(());
+        for worker in workers {
+            let worker_result = worker.join().expect("Load worker panicked");
+            if result.is_ok() {
+                result = worker_result;
+            }
+        }
+        totals.lock().unwrap().elapsed = start.elapsed();
+        result
+    })?;
+
+    Ok(totals.into_inner().unwrap())
+}
+
+#[cfg(target_os = "linux")]
+fn run_uring(job: &Job<'_>, mut offsets: OffsetGen) -> io::Result<LoadStats> {
+    use crate::backend::{IoBackend, IoUringBackend};
+
+    let depth = job.spec.queue_depth.max(1);
+    // The kernel writes into `bufs` until each read is reaped, so they must
+    // outlive every read in flight. Declared before `ring` so that `ring` is
+    // dropped first; every return path below also reaps what it queued.
+    let buf_len = aligned_span(0, job.spec.read_size, job.align).1 + job.align;
+    let mut bufs: Vec<AlignedBuf> = (0..depth)
+        .map(|_| AlignedBuf::new(buf_len, BUF_ALIGN))
+        .collect();
+    let mut ring = IoUringBackend::new(depth as u32)?;
+    ring.open(job.path, job.spec.direct)?;
+
+    // When each slot's read was issued, and how many bytes it asked for
+    let mut started = vec![(Instant::now(), 0); depth];
+    let mut stats = LoadStats::default();
+    let budget = Budget::new(job.limit);
+    let start = budget.start();
+
+    let mut issue = |ring: &mut IoUringBackend, slot: usize, started: &mut [(Instant, usize)]| {
+        let (offset, len) = aligned_span(offsets.next_offset(), job.spec.read_size, job.align);
+        started[slot] = (Instant::now(), len);
+        // Each slot's buffer is only reused after its previous read is reaped
+        unsafe { ring.queue_read(bufs[slot].as_mut_ptr(), len, offset, slot as u64) }
+    };
+
+    let mut in_flight = 0;
+    let mut run = || -> io::Result<()> {
+        for slot in 0..depth {
+            if !budget.take() {
+                break;
+            }
+            issue(&mut ring, slot, &mut started)?;
+            in_flight += 1;
+        }
+
+        while in_flight > 0 {
+            ring.submit_and_wait(1)?;
+            while let Some((slot, result)) = ring.reap() {
+                in_flight -= 1;
+                let slot = slot as usize;
+                let (issued, len) = started[slot];
+                check_full(result?, len)?;
+                stats.record(job.spec.read_size, issued.elapsed());
+                if budget.take() {
+                    issue(&mut ring, slot, &mut started)?;
+                    in_flight += 1;
+                }
+            }
+        }
+        Ok(())
+    };
+
+    if let Err(e) = run() {
+        if !drain(&mut ring, in_flight) {
+            // Reads may still land in the buffers; leak them rather than free
+            // memory the kernel writes into
+            std::mem::forget(bufs);
+        }
+        return Err(e);
+    }
+    stats.elapsed = start.elapsed();
+    ring.close()?;
+    Ok(stats)
+}
+
+/// Fail a read that returned fewer bytes than requested
+///
+/// Reads never reach the end of the file, so a short one means the file or
+/// device is smaller than it claimed; counting it would overstate throughput.
+fn check_full(n: usize, len: usize) -> io::Result<()> {
+    if n < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("short read: {} of {} bytes", n, len),
+        ));
+    }
+    Ok(())
+}
+
+/// Wait for `in_flight` queued reads to complete, discarding their results;
+/// false if the ring fails before they all do
+#[cfg(target_os = "linux")]
+fn drain(ring: &mut crate::backend::IoUringBackend, mut in_flight: usize) -> bool {
+    while in_flight > 0 {
+        if ring.submit_and_wait(1).is_err() {
+            return false;
+        }
+        while ring.reap().is_some() {
+            in_flight -= 1;
+        }
+    }
+    true
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_uring(_job: &Job<'_>, _offsets: OffsetGen) -> io::Result<LoadStats> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "io_uring is only available on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_run_has_zero_rates() {
+        let mut stats = LoadStats::default();
+        assert_eq!(stats.iops(), 0.0);
+        assert_eq!(stats.bandwidth(), 0.0);
+        stats.elapsed = Duration::from_secs(1);
+        assert_eq!(stats.iops(), 0.0);
+
+        stats.record(4096, Duration::from_micros(100));
+        stats.record(4096, Duration::from_micros(100));
+        assert_eq!(stats.iops(), 2.0);
+        assert_eq!(stats.bandwidth(), 8192.0);
+        stats.elapsed = Duration::ZERO;
+        assert_eq!(stats.iops(), 0.0);
+    }
+}
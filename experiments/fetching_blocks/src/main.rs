@@ -1,3 +1,405 @@
-fn main() {
-    println!("Hello, world!");
+//! fio-style driver for the block-fetch experiments.
+//!
+//! Runs the same random-read load as the `queue_depth_reads` benchmark
+//! against any file or block device. A job, given on the command line or as a
+//! section of a job file, is a matrix of read sizes, backends, cache modes and
+//! queue depths; every combination is one run, printed as a row named
+//! `<job>/<backend>/<cache>/qd<N>/<size>`.
+
+mod cli;
+mod compare;
+mod job;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::ExitCode;
+
+use cli::{ChartOptions, Command, Output, SimulateOptions};
+use fetching_blocks::cache::sim::Simulation;
+use fetching_blocks::chart::html_page;
+use fetching_blocks::load::{run_reads, Engine, Limit, LoadSpec, LoadStats};
+use fetching_blocks::nocache::evict_range;
+use fetching_blocks::residency::file_residency;
+use fetching_blocks::{
+    block_geometry, fingerprint, format_size, BackendKind, IoDelta, IoSnapshot, Measurement, Report,
+};
+use job::{CacheMode, Job};
+
+fn main() -> ExitCode {
+    let (jobs, output) = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(job, output)) => (vec![job], output),
+        Ok(Command::RunFile(file, output)) => match read_job_file(&file) {
+            Ok(jobs) => (jobs, output),
+            Err(e) => {
+                eprintln!("error: {}: {}", file.display(), e);
+                return ExitCode::from(2);
+            }
+        },
+        Ok(Command::Compare(options)) => {
+            return match compare::compare(&options) {
+                Ok(false) => ExitCode::SUCCESS,
+                Ok(true) => ExitCode::FAILURE,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    ExitCode::from(2)
+                }
+            };
+        }
+        Ok(Command::Chart(options)) => {
+            return match draw_chart(&options) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    ExitCode::from(2)
+                }
+            };
+        }
+        Ok(Command::Simulate(options)) => {
+            return match simulate(&options) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    ExitCode::from(2)
+                }
+            };
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut report = Report::new("fetching_blocks");
+    for (i, job) in jobs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        report
+            .environment
+            .add_path(job.path.as_deref().expect("job without a path"));
+        match run_job(job) {
+            Ok(runs) => report
+                .measurements
+                .extend(runs.iter().map(RunResult::to_measurement)),
+            Err(e) => {
+                eprintln!("error: job '{}': {}", job.name, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    match save_report(&report, &output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Write the requested result files
+fn save_report(report: &Report, output: &Output) -> Result<(), String> {
+    let write = |path: &Path, contents: String| {
+        std::fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    if let Some(path) = &output.json {
+        write(path, format!("{}\n", report.to_json()))?;
+    }
+    if let Some(path) = &output.csv {
+        write(path, report.to_csv())?;
+    }
+    Ok(())
+}
+
+/// Render a chart of a saved result file, as SVG or, for a `.html` output,
+/// as a page holding it
+fn draw_chart(options: &ChartOptions) -> Result<(), String> {
+    let results = &options.results;
+    let report = Report::load(results).map_err(|e| format!("{}: {}", results.display(), e))?;
+    let svg = options.chart.render(&report.measurements)?;
+    let Some(path) = &options.output else {
+        print!("{}", svg);
+        return Ok(());
+    };
+    let contents = if path.extension().is_some_and(|ext| ext == "html") {
+        html_page(&options.chart.title, &[svg])
+    } else {
+        svg
+    };
+    std::fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Replay a trace through every policy and capacity, printing a table of
+/// miss ratios with one column per policy
+fn simulate(options: &SimulateOptions) -> Result<(), String> {
+    let mut simulation =
+        Simulation::new(options.block_size, &options.policies, &options.capacities);
+    let trace = &options.trace;
+    let replayed = if trace.as_os_str() == "-" {
+        simulation.replay(io::stdin().lock())
+    } else {
+        File::open(trace)
+            .map_err(|e| e.to_string())
+            .and_then(|file| simulation.replay(io::BufReader::new(file)))
+    };
+    replayed.map_err(|e| format!("{}: {}", trace.display(), e))?;
+
+    let block_size = options.block_size as usize;
+    let accesses = simulation.accesses();
+    println!(
+        "{}: {} reads, {} accesses of {} blocks, {} unique blocks (compulsory miss ratio {:.3})",
+        trace.display(),
+        simulation.reads(),
+        accesses,
+        format_size(block_size),
+        simulation.unique_blocks(),
+        simulation.unique_blocks() as f64 / accesses.max(1) as f64
+    );
+    println!();
+    print!("{:>10} {:>10}", "capacity", "blocks");
+    for policy in &options.policies {
+        print!(" {:>8}", policy.name());
+    }
+    println!();
+    let results = simulation.results();
+    for (i, &capacity) in options.capacities.iter().enumerate() {
+        print!(
+            "{:>10} {:>10}",
+            format_size(capacity * block_size),
+            capacity
+        );
+        // Results are grouped by policy, each with every capacity
+        for p in 0..options.policies.len() {
+            print!(
+                " {:>8.4}",
+                results[p * options.capacities.len() + i].miss_ratio()
+            );
+        }
+        println!();
+    }
+
+    if let Some(path) = &options.csv {
+        let mut csv =
+            String::from("policy,capacity_blocks,capacity_bytes,hits,misses,miss_ratio\n");
+        for result in results {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{:.6}\n",
+                result.policy.name(),
+                result.capacity,
+                result.capacity * block_size,
+                result.hits,
+                result.misses,
+                result.miss_ratio()
+            ));
+        }
+        std::fs::write(path, csv).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn read_job_file(file: &Path) -> Result<Vec<Job>, String> {
+    let text = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+    job::parse_job_file(&text)
+}
+
+/// The outcome of one combination of a job's matrix
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    pub job: String,
+    pub backend: BackendKind,
+    pub cache: CacheMode,
+    pub queue_depth: usize,
+    pub read_size: usize,
+    pub stats: LoadStats,
+    pub io: IoDelta,
+    /// Fraction of the file cached when the run started; `None` for direct
+    pub cached: Option<f64>,
+}
+
+impl RunResult {
+    /// `<job>/<backend>/<cache>/qd<N>/<size>`, unique within a job file
+    pub fn name(&self) -> String {
+        format!(
+            "{}/{}/{}/qd{}/{}",
+            self.job,
+            self.backend.name(),
+            self.cache.name(),
+            self.queue_depth,
+            format_size(self.read_size)
+        )
+    }
+
+    pub fn to_measurement(&self) -> Measurement {
+        let mut params = Vec::new();
+        if let Some(cached) = self.cached {
+            params.push(("cached".to_string(), format!("{:.3}", cached)));
+        }
+        Measurement {
+            group: self.job.clone(),
+            name: self.name(),
+            backend: self.backend.name().to_string(),
+            cache: self.cache.name().to_string(),
+            params,
+            io: Some(self.io.clone()),
+            ..Measurement::from_load(&self.stats, self.read_size, self.queue_depth)
+        }
+    }
+}
+
+/// Run every combination of the job's lists, printing a row as each finishes
+fn run_job(job: &Job) -> io::Result<Vec<RunResult>> {
+    let path = job.path.as_deref().expect("job without a path");
+    let geometry = block_geometry(path)?;
+    let sizes = if job.sizes.is_empty() {
+        geometry.read_sizes()
+    } else {
+        job.sizes.clone()
+    };
+    let block_size = job.block_size.unwrap_or(geometry.fetch_unit() as u64);
+
+    println!(
+        "[{}] {}: device {}, logical block {}, physical block {}",
+        job.name,
+        path.display(),
+        geometry.device.as_deref().unwrap_or("unknown"),
+        format_size(geometry.logical_block_size),
+        format_size(geometry.physical_block_size),
+    );
+    let environment = fingerprint(path);
+    if let Some(storage) = environment.storage.first() {
+        let unknown = || "unknown".to_string();
+        println!(
+            "kernel {}, fs {} ({}), scheduler {}, rotational {}",
+            environment.kernel.clone().unwrap_or_else(unknown),
+            storage
+                .fs_type
+                .clone()
+                .unwrap_or_else(|| "none".to_string()),
+            storage.mount_options.clone().unwrap_or_else(unknown),
+            storage.scheduler.clone().unwrap_or_else(unknown),
+            storage.rotational.map_or_else(unknown, |r| r.to_string()),
+        );
+    }
+    println!(
+        "pattern {}, offsets every {}, {}",
+        job.pattern.name(),
+        format_size(block_size as usize),
+        describe_limit(job.limit),
+    );
+    println!();
+    print_header();
+
+    let mut results = Vec::new();
+    for &backend in &job.backends {
+        for &cache in &job.caches {
+            for &queue_depth in &job.queue_depths {
+                for &read_size in &sizes {
+                    let spec = LoadSpec {
+                        read_size,
+                        queue_depth,
+                        pattern: job.pattern,
+                        block_size,
+                        direct: cache == CacheMode::Direct,
+                        seed: job.seed,
+                    };
+                    let cached = prepare_cache(path, cache)?;
+                    let before = IoSnapshot::take(path)?;
+                    let stats = run_reads(path, &spec, engine_for(backend), job.limit)?;
+                    let io = before.delta(&IoSnapshot::take(path)?);
+                    let result = RunResult {
+                        job: job.name.clone(),
+                        backend,
+                        cache,
+                        queue_depth,
+                        read_size,
+                        stats,
+                        io,
+                        cached,
+                    };
+                    print_row(&result);
+                    results.push(result);
+                }
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// `io_uring` gets its own single-ring engine; every other backend is
+/// driven by one thread per outstanding read
+fn engine_for(backend: BackendKind) -> Engine {
+    match backend {
+        BackendKind::IoUring => Engine::IoUring,
+        kind => Engine::Threads(kind),
+    }
+}
+
+fn describe_limit(limit: Limit) -> String {
+    match limit {
+        Limit::Reads(n) => format!("{} reads per run", n),
+        Limit::Duration(d) => format!("{:?} per run", d),
+    }
+}
+
+/// Put the page cache in the run's starting state
+///
+/// Returns the fraction of the file that is cached afterwards, for the
+/// buffered modes where it matters.
+fn prepare_cache(path: &Path, cache: CacheMode) -> io::Result<Option<f64>> {
+    match cache {
+        CacheMode::Direct => return Ok(None),
+        CacheMode::Buffered => {}
+        CacheMode::Cold => evict_range(&File::open(path)?, 0, 0)?,
+        CacheMode::Warm => {
+            let mut file = File::open(path)?;
+            let mut buffer = vec![0u8; 1 << 20];
+            while file.read(&mut buffer)? > 0 {}
+        }
+    }
+    Ok(Some(file_residency(path)?.fraction()))
+}
+
+fn print_header() {
+    println!(
+        "{:<36} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+        "run",
+        "IOPS",
+        "MB/s",
+        "mean_us",
+        "p50_us",
+        "p99_us",
+        "p99.9_us",
+        "max_us",
+        "sect/read",
+        "cached"
+    );
+}
+
+fn print_row(result: &RunResult) {
+    let us = |ns: u64| ns as f64 / 1e3;
+    let stats = &result.stats;
+    let p = stats.latency.percentiles();
+    println!(
+        "{:<36} {:>10.0} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10} {:>8}",
+        result.name(),
+        stats.iops(),
+        stats.bandwidth() / 1e6,
+        stats.mean_latency().as_secs_f64() * 1e6,
+        us(p.p50),
+        us(p.p99),
+        us(p.p999),
+        us(p.max),
+        result
+            .io
+            .sectors_per_read(stats.reads)
+            .map_or("-".to_string(), |s| format!("{:.1}", s)),
+        result
+            .cached
+            .map_or("-".to_string(), |c| format!("{:.0}%", c * 100.0)),
+    );
 }
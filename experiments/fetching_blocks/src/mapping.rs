@@ -0,0 +1,167 @@
+//! Read-only shared file mappings with control over how pages fault in.
+//!
+//! Reading through a mapping has no read syscall to time: the cost is the
+//! page faults taken when the mapping is first touched. `MapOptions` selects
+//! whether those faults happen up front (`MAP_POPULATE`), as the pages are
+//! touched, or after a readahead hint (`madvise`).
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+/// `madvise` hint applied to a mapping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapAdvice {
+    /// No hint: faults read around the faulting page (`MADV_NORMAL`)
+    #[default]
+    Normal,
+    /// Each fault reads only its own page (`MADV_RANDOM`)
+    Random,
+    /// Start reading the whole mapping in the background (`MADV_WILLNEED`)
+    WillNeed,
+    /// Back the mapping with transparent huge pages where possible
+    /// (`MADV_HUGEPAGE`, Linux only)
+    HugePage,
+}
+
+impl MapAdvice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapAdvice::Normal => "normal",
+            MapAdvice::Random => "random",
+            MapAdvice::WillNeed => "willneed",
+            MapAdvice::HugePage => "hugepage",
+        }
+    }
+
+    /// The `madvise` flag, if the platform has one
+    fn flag(&self) -> Option<libc::c_int> {
+        match self {
+            MapAdvice::Normal => Some(libc::MADV_NORMAL),
+            MapAdvice::Random => Some(libc::MADV_RANDOM),
+            MapAdvice::WillNeed => Some(libc::MADV_WILLNEED),
+            #[cfg(target_os = "linux")]
+            MapAdvice::HugePage => Some(libc::MADV_HUGEPAGE),
+            #[cfg(not(target_os = "linux"))]
+            MapAdvice::HugePage => None,
+        }
+    }
+}
+
+/// How a `Mapping` is set up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MapOptions {
+    /// Fault every page in while mapping (`MAP_POPULATE`, Linux only)
+    pub populate: bool,
+    pub advice: MapAdvice,
+}
+
+/// A read-only `MAP_SHARED` mapping of part of a file
+///
+/// The file stays open for the lifetime of the mapping and the range is
+/// unmapped on drop.
+pub struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+    _file: File,
+}
+
+// The mapping is read-only and owned exclusively by its holder.
+unsafe impl Send for Mapping {}
+
+impl Mapping {
+    /// Map `len` bytes of `file` starting at `offset`, which must be page-aligned
+    pub fn new(file: File, offset: u64, len: usize, options: &MapOptions) -> io::Result<Mapping> {
+        if len == 0 {
+            return Ok(Mapping {
+                addr: ptr::null_mut(),
+                len,
+                _file: file,
+            });
+        }
+
+        #[cfg(target_os = "linux")]
+        let flags = if options.populate {
+            libc::MAP_SHARED | libc::MAP_POPULATE
+        } else {
+            libc::MAP_SHARED
+        };
+        #[cfg(not(target_os = "linux"))]
+        let flags = if options.populate {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "MAP_POPULATE is only available on Linux",
+            ));
+        } else {
+            libc::MAP_SHARED
+        };
+
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                flags,
+                file.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mapping = Mapping {
+            addr,
+            len,
+            _file: file,
+        };
+        if options.advice != MapAdvice::Normal {
+            mapping.advise(options.advice)?;
+        }
+        Ok(mapping)
+    }
+
+    /// Apply `advice` to the whole mapping
+    pub fn advise(&self, advice: MapAdvice) -> io::Result<()> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        let flag = advice.flag().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "madvise {} is not available on this platform",
+                    advice.name()
+                ),
+            )
+        })?;
+        if unsafe { libc::madvise(self.addr, self.len, flag) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The mapped bytes; touching a non-resident page faults it in
+    pub fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.addr, self.len) };
+        }
+    }
+}
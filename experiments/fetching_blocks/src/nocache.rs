@@ -0,0 +1,140 @@
+//! Opening files so that reads bypass the OS page cache, and evicting them
+//! from it so buffered reads start cold.
+
+use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::fs::OpenOptions;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Open a file with F_NOCACHE to bypass OS cache (macOS only)
+#[cfg(target_os = "macos")]
+pub fn open_nocache(path: &Path) -> std::io::Result<File> {
+    let file = File::open(path)?;
+    unsafe {
+        if libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(file)
+}
+
+/// Open a file with O_DIRECT to bypass OS cache (Linux only)
+///
+/// O_DIRECT requires the buffer address, length and file offset to be
+/// multiples of the device's logical block size; see `dio_alignment`.
+#[cfg(target_os = "linux")]
+pub fn open_nocache(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+/// Open an existing file for writing with F_NOCACHE (macOS only)
+#[cfg(target_os = "macos")]
+pub fn open_nocache_write(path: &Path) -> std::io::Result<File> {
+    let file = std::fs::OpenOptions::new().write(true).open(path)?;
+    unsafe {
+        if libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(file)
+}
+
+/// Open an existing file for writing with O_DIRECT (Linux only)
+///
+/// The same alignment rules as `open_nocache` apply to writes.
+#[cfg(target_os = "linux")]
+pub fn open_nocache_write(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+/// Alignment required for direct I/O on this file
+///
+/// Uses `statx(STATX_DIOALIGN)` where the kernel supports it (6.1+), otherwise
+/// falls back to 512 bytes, the smallest logical block size in practice.
+#[cfg(target_os = "linux")]
+pub fn dio_alignment(file: &File) -> usize {
+    unsafe {
+        let mut stx: libc::statx = std::mem::zeroed();
+        let rc = libc::statx(
+            file.as_raw_fd(),
+            c"".as_ptr(),
+            libc::AT_EMPTY_PATH,
+            libc::STATX_DIOALIGN,
+            &mut stx,
+        );
+        if rc == 0 && stx.stx_mask & libc::STATX_DIOALIGN != 0 {
+            let align = stx.stx_dio_offset_align.max(stx.stx_dio_mem_align) as usize;
+            if align > 0 {
+                return align;
+            }
+        }
+    }
+    512
+}
+
+/// F_NOCACHE has no alignment requirements
+#[cfg(target_os = "macos")]
+pub fn dio_alignment(_file: &File) -> usize {
+    1
+}
+
+/// Drop the cached pages of `[offset, offset + len)` of `file` (Linux only)
+///
+/// Uses `posix_fadvise(POSIX_FADV_DONTNEED)`, which only drops clean pages, so
+/// sync dirty data first. A `len` of 0 means "to the end of the file".
+#[cfg(target_os = "linux")]
+pub fn evict_range(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    let rc = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            libc::POSIX_FADV_DONTNEED,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::from_raw_os_error(rc));
+    }
+    Ok(())
+}
+
+/// macOS cannot drop a single file's clean pages, so this does nothing; files
+/// written and read with F_NOCACHE never enter the cache in the first place
+#[cfg(target_os = "macos")]
+pub fn evict_range(_file: &File, _offset: u64, _len: u64) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Flush dirty data and drop the whole page cache via `/proc/sys/vm/drop_caches`
+///
+/// Heavier than `evict_range` (every file's pages go, and the next reads of
+/// anything are slow) but also clears metadata the benchmark's file depends
+/// on. Needs root; fails with `PermissionDenied` otherwise.
+#[cfg(target_os = "linux")]
+pub fn drop_caches() -> std::io::Result<()> {
+    if unsafe { libc::geteuid() } != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "dropping the page cache requires root",
+        ));
+    }
+    unsafe { libc::sync() };
+    std::fs::write("/proc/sys/vm/drop_caches", "3\n")
+}
+
+#[cfg(target_os = "macos")]
+pub fn drop_caches() -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "drop_caches is only available on Linux",
+    ))
+}
@@ -0,0 +1,478 @@
+//! Machine-readable results: every measurement as JSON and CSV.
+//!
+//! The console tables and Criterion's report are for reading; these files
+//! are for post-processing and plotting. A `Report` holds the environment it
+//! ran in and one `Measurement` per configuration, and writes both formats
+//! side by side. JSON keeps everything, including device counters; CSV flattens each
+//! measurement into one row with a fixed set of columns.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::environment::Environment;
+use crate::histogram::Histogram;
+use crate::iostats::{DiskStats, IoDelta, ProcessIo};
+use crate::json::Json;
+use crate::load::LoadStats;
+
+/// Latency distribution of a measurement, in nanoseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl LatencySummary {
+    pub fn from_histogram(latency: &Histogram) -> Self {
+        let p = latency.percentiles();
+        LatencySummary {
+            count: latency.count(),
+            mean: latency.mean(),
+            stddev: latency.stddev(),
+            min: latency.min(),
+            p50: p.p50,
+            p90: p.p90,
+            p99: p.p99,
+            p999: p.p999,
+            max: p.max,
+        }
+    }
+
+    fn from_json(json: &Json) -> Result<Self, String> {
+        let field = |key: &str| {
+            json.get(key)
+                .and_then(Json::as_f64)
+                .ok_or_else(|| format!("latency: missing '{}'", key))
+        };
+        Ok(LatencySummary {
+            count: field("count")? as u64,
+            mean: field("mean_ns")?,
+            stddev: field("stddev_ns")?,
+            min: field("min_ns")? as u64,
+            p50: field("p50_ns")? as u64,
+            p90: field("p90_ns")? as u64,
+            p99: field("p99_ns")? as u64,
+            p999: field("p999_ns")? as u64,
+            max: field("max_ns")? as u64,
+        })
+    }
+
+    fn to_json(self) -> Json {
+        Json::object([
+            ("count", self.count.into()),
+            ("mean_ns", self.mean.into()),
+            ("stddev_ns", self.stddev.into()),
+            ("min_ns", self.min.into()),
+            ("p50_ns", self.p50.into()),
+            ("p90_ns", self.p90.into()),
+            ("p99_ns", self.p99.into()),
+            ("p999_ns", self.p999.into()),
+            ("max_ns", self.max.into()),
+        ])
+    }
+}
+
+/// One measured configuration
+#[derive(Debug, Clone, Default)]
+pub struct Measurement {
+    /// Benchmark group or job the measurement belongs to
+    pub group: String,
+    /// Unique within a report, e.g. `random_subblock_reads/uniform/2KB`
+    pub name: String,
+    /// Bytes per operation
+    pub size: usize,
+    /// Offset of every operation, or of each one within its block; `None`
+    /// when offsets are random
+    pub offset: Option<u64>,
+    /// How the data was read, e.g. `pread`, `io_uring` or `mmap`
+    pub backend: String,
+    /// Page-cache state: `direct`, `buffered`, `cold` or `warm`
+    pub cache: String,
+    pub queue_depth: usize,
+    /// Settings specific to the group, e.g. `("pattern", "zipfian")`
+    pub params: Vec<(String, String)>,
+    pub operations: u64,
+    pub bytes: u64,
+    /// Time the operations took: wall-clock time for concurrent loads, the
+    /// sum of latencies for one-at-a-time benchmarks
+    pub elapsed: Duration,
+    pub latency: Option<LatencySummary>,
+    /// Kernel I/O counters over the measurement
+    pub io: Option<IoDelta>,
+}
+
+impl Measurement {
+    /// Operations, bytes, elapsed time and latency from a one-at-a-time
+    /// benchmark's histogram; the caller fills in the rest
+    pub fn from_histogram(latency: &Histogram, size: usize) -> Self {
+        Measurement {
+            size,
+            queue_depth: 1,
+            operations: latency.count(),
+            bytes: latency.count() * size as u64,
+            elapsed: Duration::from_nanos(latency.sum().min(u64::MAX as u128) as u64),
+            latency: Some(LatencySummary::from_histogram(latency)),
+            ..Measurement::default()
+        }
+    }
+
+    /// The same from a concurrent load run
+    pub fn from_load(stats: &LoadStats, size: usize, queue_depth: usize) -> Self {
+        Measurement {
+            size,
+            queue_depth,
+            operations: stats.reads,
+            bytes: stats.bytes,
+            elapsed: stats.elapsed,
+            latency: Some(LatencySummary::from_histogram(&stats.latency)),
+            ..Measurement::default()
+        }
+    }
+
+    /// Operations per second, 0 when nothing ran
+    pub fn iops(&self) -> f64 {
+        self.per_second(self.operations)
+    }
+
+    /// Bytes per second, 0 when nothing ran
+    pub fn bandwidth(&self) -> f64 {
+        self.per_second(self.bytes)
+    }
+
+    fn per_second(&self, count: u64) -> f64 {
+        if self.operations == 0 || self.elapsed.is_zero() {
+            return 0.0;
+        }
+        count as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("group", self.group.as_str().into()),
+            ("name", self.name.as_str().into()),
+            ("size", self.size.into()),
+            ("offset", self.offset.into()),
+            ("backend", self.backend.as_str().into()),
+            ("cache", self.cache.as_str().into()),
+            ("queue_depth", self.queue_depth.into()),
+            (
+                "params",
+                Json::object(
+                    self.params
+                        .iter()
+                        .map(|(k, v)| (k.as_str(), v.as_str().into())),
+                ),
+            ),
+            ("operations", self.operations.into()),
+            ("bytes", self.bytes.into()),
+            ("elapsed_s", self.elapsed.as_secs_f64().into()),
+            ("iops", self.iops().into()),
+            ("bandwidth_bps", self.bandwidth().into()),
+            ("latency", self.latency.map(LatencySummary::to_json).into()),
+            ("io", self.io.as_ref().map(io_to_json).into()),
+        ])
+    }
+
+    /// Read back a measurement written by `to_json`
+    ///
+    /// Device counters are not read back; `io` is always `None`.
+    pub fn from_json(json: &Json) -> Result<Self, String> {
+        let name = json
+            .get("name")
+            .and_then(Json::as_str)
+            .ok_or("measurement without a name")?;
+        let missing = |key: &str| format!("{}: missing '{}'", name, key);
+        let string = |key: &str| {
+            json.get(key)
+                .and_then(Json::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| missing(key))
+        };
+        let number = |key: &str| {
+            json.get(key)
+                .and_then(Json::as_u64)
+                .ok_or_else(|| missing(key))
+        };
+
+        let params = match json.get("params") {
+            Some(Json::Object(fields)) => fields
+                .iter()
+                .map(|(k, v)| {
+                    let v = v
+                        .as_str()
+                        .ok_or_else(|| missing(&format!("params.{}", k)))?;
+                    Ok((k.clone(), v.to_string()))
+                })
+                .collect::<Result<_, String>>()?,
+            _ => Vec::new(),
+        };
+        let elapsed = json
+            .get("elapsed_s")
+            .and_then(Json::as_f64)
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            .ok_or_else(|| missing("elapsed_s"))?;
+        let latency = match json.get("latency") {
+            None | Some(Json::Null) => None,
+            Some(latency) => {
+                Some(LatencySummary::from_json(latency).map_err(|e| format!("{}: {}", name, e))?)
+            }
+        };
+
+        Ok(Measurement {
+            group: string("group")?,
+            name: name.to_string(),
+            size: number("size")? as usize,
+            offset: json.get("offset").and_then(Json::as_u64),
+            backend: string("backend")?,
+            cache: string("cache")?,
+            queue_depth: number("queue_depth")? as usize,
+            params,
+            operations: number("operations")?,
+            bytes: number("bytes")?,
+            elapsed,
+            latency,
+            io: None,
+        })
+    }
+}
+
+fn io_to_json(io: &IoDelta) -> Json {
+    let disk = |d: &DiskStats| {
+        Json::object([
+            ("reads_completed", d.reads_completed.into()),
+            ("reads_merged", d.reads_merged.into()),
+            ("sectors_read", d.sectors_read.into()),
+            ("read_time_ms", d.read_time_ms.into()),
+            ("writes_completed", d.writes_completed.into()),
+            ("writes_merged", d.writes_merged.into()),
+            ("sectors_written", d.sectors_written.into()),
+            ("write_time_ms", d.write_time_ms.into()),
+        ])
+    };
+    let process = |p: &ProcessIo| {
+        Json::object([
+            ("rchar", p.rchar.into()),
+            ("wchar", p.wchar.into()),
+            ("syscr", p.syscr.into()),
+            ("syscw", p.syscw.into()),
+            ("read_bytes", p.read_bytes.into()),
+            ("write_bytes", p.write_bytes.into()),
+        ])
+    };
+    Json::object([
+        ("disk", io.disk.as_ref().map(disk).into()),
+        ("process", io.process.as_ref().map(process).into()),
+        ("elapsed_s", io.elapsed.as_secs_f64().into()),
+    ])
+}
+
+/// Measurements from one invocation, with where and when they were taken
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// What produced the report, e.g. `disk_io` or `fetching_blocks`
+    pub tool: String,
+    /// Host, kernel and storage the measurements were taken on
+    pub environment: Environment,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    pub measurements: Vec<Measurement>,
+}
+
+/// Columns of `Report::to_csv`, in order
+const CSV_COLUMNS: [&str; 35] = [
+    "hostname",
+    "kernel",
+    "cpu_model",
+    "device",
+    "device_model",
+    "rotational",
+    "fs_type",
+    "mount_options",
+    "scheduler",
+    "group",
+    "name",
+    "size",
+    "offset",
+    "backend",
+    "cache",
+    "queue_depth",
+    "params",
+    "operations",
+    "bytes",
+    "elapsed_s",
+    "iops",
+    "bandwidth_bps",
+    "mean_ns",
+    "stddev_ns",
+    "min_ns",
+    "p50_ns",
+    "p90_ns",
+    "p99_ns",
+    "p999_ns",
+    "max_ns",
+    "disk_reads",
+    "disk_sectors_read",
+    "disk_writes",
+    "disk_sectors_written",
+    "process_read_bytes",
+];
+
+impl Report {
+    /// An empty report for `tool` on the current host, timestamped now
+    ///
+    /// Add the paths being measured to `environment` as they are used.
+    pub fn new(tool: &str) -> Self {
+        Report {
+            tool: tool.to_string(),
+            environment: Environment::host(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            measurements: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("tool", self.tool.as_str().into()),
+            ("created", self.created.into()),
+            ("environment", self.environment.to_json()),
+            (
+                "measurements",
+                Json::Array(self.measurements.iter().map(Measurement::to_json).collect()),
+            ),
+        ])
+    }
+
+    /// Read back a report written by `to_json`
+    pub fn from_json(json: &Json) -> Result<Self, String> {
+        let measurements = json
+            .get("measurements")
+            .and_then(Json::as_array)
+            .ok_or("missing 'measurements'")?
+            .iter()
+            .map(Measurement::from_json)
+            .collect::<Result<_, _>>()?;
+        Ok(Report {
+            tool: json
+                .get("tool")
+                .and_then(Json::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            environment: json
+                .get("environment")
+                .map(Environment::from_json)
+                .unwrap_or_default(),
+            created: json.get("created").and_then(Json::as_u64).unwrap_or(0),
+            measurements,
+        })
+    }
+
+    /// Load a report from a JSON result file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Report::from_json(&Json::parse(&text)?)
+    }
+
+    /// One header row, then one row per measurement
+    ///
+    /// Every row starts with the environment, so rows from different files
+    /// can be concatenated; storage columns list each distinct value across
+    /// the report's storage, joined with `;`. `params` are joined as
+    /// `key=value;key=value`; missing values, such as latency for benchmarks
+    /// that only time whole batches, are empty cells.
+    pub fn to_csv(&self) -> String {
+        let env = &self.environment;
+        let environment = [
+            env.hostname.clone(),
+            env.kernel.clone().unwrap_or_default(),
+            env.cpu_model.clone().unwrap_or_default(),
+            env.storage_summary(|s| s.device.clone()),
+            env.storage_summary(|s| s.model.clone()),
+            env.storage_summary(|s| s.rotational.map(|r| r.to_string())),
+            env.storage_summary(|s| s.fs_type.clone()),
+            env.storage_summary(|s| s.mount_options.clone()),
+            env.storage_summary(|s| s.scheduler.clone()),
+        ];
+
+        let mut out = CSV_COLUMNS.join(",");
+        out.push('\n');
+        for m in &self.measurements {
+            let disk = m.io.as_ref().and_then(|io| io.disk);
+            let process = m.io.as_ref().and_then(|io| io.process);
+            let opt = |v: Option<u64>| v.map_or(String::new(), |v| v.to_string());
+            let float = |v: Option<f64>| v.map_or(String::new(), |v| format!("{:.1}", v));
+            let params: Vec<String> = m
+                .params
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+            let row = [
+                m.group.clone(),
+                m.name.clone(),
+                m.size.to_string(),
+                opt(m.offset),
+                m.backend.clone(),
+                m.cache.clone(),
+                m.queue_depth.to_string(),
+                params.join(";"),
+                m.operations.to_string(),
+                m.bytes.to_string(),
+                m.elapsed.as_secs_f64().to_string(),
+                float(Some(m.iops())),
+                float(Some(m.bandwidth())),
+                float(m.latency.map(|l| l.mean)),
+                float(m.latency.map(|l| l.stddev)),
+                opt(m.latency.map(|l| l.min)),
+                opt(m.latency.map(|l| l.p50)),
+                opt(m.latency.map(|l| l.p90)),
+                opt(m.latency.map(|l| l.p99)),
+                opt(m.latency.map(|l| l.p999)),
+                opt(m.latency.map(|l| l.max)),
+                opt(disk.map(|d| d.reads_completed)),
+                opt(disk.map(|d| d.sectors_read)),
+                opt(disk.map(|d| d.writes_completed)),
+                opt(disk.map(|d| d.sectors_written)),
+                opt(process.map(|p| p.read_bytes)),
+            ];
+            let cells: Vec<String> = environment
+                .iter()
+                .chain(&row)
+                .map(|cell| csv_escape(cell))
+                .collect();
+            let _ = writeln!(out, "{}", cells.join(","));
+        }
+        out
+    }
+
+    /// Write `<stem>.json` and `<stem>.csv` into `dir`, creating it if needed
+    ///
+    /// Returns the paths written.
+    pub fn save(&self, dir: &Path, stem: &str) -> io::Result<(PathBuf, PathBuf)> {
+        fs::create_dir_all(dir)?;
+        let json = dir.join(format!("{}.json", stem));
+        let csv = dir.join(format!("{}.csv", stem));
+        fs::write(&json, format!("{}\n", self.to_json()))?;
+        fs::write(&csv, self.to_csv())?;
+        Ok((json, csv))
+    }
+}
+
+/// Quote a CSV cell if it contains a separator, quote or line break
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
@@ -0,0 +1,96 @@
+//! Page-cache residency of file ranges, via `mmap` + `mincore`.
+//!
+//! Cold-read benchmarks assume the pages they read are not cached and warm
+//! ones assume they are. `residency` checks instead of hoping: it maps the
+//! range without touching it and asks the kernel which of its pages are in
+//! the page cache. Mapping alone faults nothing in, so the check does not
+//! disturb the state it reports.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::buf::aligned_span;
+use crate::file_size;
+
+/// How many pages of a file range are in the page cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Residency {
+    /// Pages the range covers
+    pub pages: usize,
+    /// Pages currently cached
+    pub resident: usize,
+}
+
+impl Residency {
+    /// No page of the range is cached
+    pub fn is_cold(&self) -> bool {
+        self.resident == 0
+    }
+
+    /// Every page of the range is cached
+    pub fn is_warm(&self) -> bool {
+        self.resident == self.pages
+    }
+
+    /// Fraction of the range's pages that are cached; 1.0 for an empty range
+    pub fn fraction(&self) -> f64 {
+        if self.pages == 0 {
+            return 1.0;
+        }
+        self.resident as f64 / self.pages as f64
+    }
+}
+
+pub fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Residency of the pages covering `[offset, offset + len)` of `file`
+///
+/// The range is clamped to the end of the file, since pages past it cannot be
+/// mapped. Any open mode works, including `O_DIRECT`, and `file` may be a
+/// block device.
+pub fn residency(file: &File, offset: u64, len: usize) -> io::Result<Residency> {
+    let size = file_size(file)?;
+    let end = offset.saturating_add(len as u64).min(size);
+    if end <= offset {
+        return Ok(Residency::default());
+    }
+    let (start, map_len) = aligned_span(offset, (end - offset) as usize, page_size());
+    let pages = map_len / page_size();
+
+    unsafe {
+        let addr = libc::mmap(
+            std::ptr::null_mut(),
+            map_len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            start as libc::off_t,
+        );
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mut vec = vec![0u8; pages];
+        let rc = libc::mincore(addr, map_len, vec.as_mut_ptr() as _);
+        let err = io::Error::last_os_error();
+        libc::munmap(addr, map_len);
+        if rc != 0 {
+            return Err(err);
+        }
+        Ok(Residency {
+            pages,
+            // Bit 0 is "resident"; the other bits are platform-specific
+            resident: vec.iter().filter(|&&b| b & 1 != 0).count(),
+        })
+    }
+}
+
+/// Residency of the whole file at `path`
+pub fn file_residency(path: &Path) -> io::Result<Residency> {
+    let file = File::open(path)?;
+    let len = file_size(&file)?;
+    residency(&file, 0, len as usize)
+}
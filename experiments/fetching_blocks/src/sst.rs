@@ -0,0 +1,705 @@
+//! Sorted string table data blocks, in the format RocksDB and LevelDB use.
+//!
+//! A data block holds sorted key/value entries. Each key is stored as the
+//! length of the prefix it shares with the previous key plus the rest, so
+//! runs of similar keys (`user:000123`, `user:000124`) cost little. Every
+//! `restart_interval` entries the prefix compression restarts with a full
+//! key, and the offsets of these restart points follow the entries, so a
+//! lookup binary-searches the restart keys and then scans at most one
+//! interval. A trailer with a checksum closes the block:
+//!
+//! ```text
+//! entry:   shared: varint | unshared: varint | value_len: varint | key[shared..] | value
+//! block:   entry* | restart offset: u32 LE * n | n: u32 LE | type: u8 | crc32c: u32 LE
+//! ```
+//!
+//! The type byte is always 0 (uncompressed); the checksum covers the rest
+//! of the block and the type byte, masked as RocksDB masks its CRCs.
+//! `BlockBuilder` closes a block once it reaches a target size, so a table is
+//! a sequence of blocks of roughly that size: the unit a read fetches.
+
+/// Bytes after the restart array: the type byte and the checksum
+pub const TRAILER_SIZE: usize = 5;
+
+/// Type byte of an uncompressed block
+const NO_COMPRESSION: u8 = 0;
+
+/// How blocks are laid out and when they are closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockOptions {
+    /// Size a block is filled to, in bytes, trailer included
+    pub block_size: usize,
+    /// Entries between full keys; 1 disables prefix compression
+    pub restart_interval: usize,
+    /// Close a block early, rather than let the next entry push it past
+    /// `block_size`, once it is within this percentage (0 to 100) of it
+    pub size_deviation: usize,
+}
+
+/// RocksDB's defaults: 4KB blocks, a restart every 16 keys, 10% deviation
+impl Default for BlockOptions {
+    fn default() -> Self {
+        BlockOptions {
+            block_size: 4096,
+            restart_interval: 16,
+            size_deviation: 10,
+        }
+    }
+}
+
+/// Builds one block at a time from keys added in increasing order
+pub struct BlockBuilder {
+    options: BlockOptions,
+    buffer: Vec<u8>,
+    restarts: Vec<u32>,
+    /// Entries since the last restart point
+    counter: usize,
+    entries: usize,
+    /// Last key added, in this block or the previous one
+    last_key: Vec<u8>,
+    /// Whether any entry was added since the builder was created
+    started: bool,
+}
+
+impl BlockBuilder {
+    /// Panics if the restart interval is 0 or the size deviation is over 100
+    pub fn new(options: BlockOptions) -> Self {
+        assert!(
+            options.restart_interval > 0,
+            "Restart interval must be positive"
+        );
+        assert!(
+            options.size_deviation <= 100,
+            "Size deviation must be a percentage"
+        );
+        BlockBuilder {
+            options,
+            buffer: Vec::with_capacity(options.block_size),
+            restarts: vec![0],
+            counter: 0,
+            entries: 0,
+            last_key: Vec::new(),
+            started: false,
+        }
+    }
+
+    pub fn options(&self) -> BlockOptions {
+        self.options
+    }
+
+    /// Entries in the block being built
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Size the block would have if finished now
+    pub fn current_size(&self) -> usize {
+        self.buffer.len() + 4 * self.restarts.len() + 4 + TRAILER_SIZE
+    }
+
+    /// Whether to finish the block before adding `key` and `value`
+    ///
+    /// True once the block has reached the target size, or when the entry
+    /// would take it past the target and it is already within
+    /// `size_deviation` percent of it (RocksDB's flush policy). A block always
+    /// takes at least one entry, so an entry larger than the target gets a
+    /// block of its own.
+    pub fn should_finish(&self, key: &[u8], value: &[u8]) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let size = self.current_size();
+        let target = self.options.block_size;
+        if size >= target {
+            return true;
+        }
+        if self.options.size_deviation == 0 {
+            return false;
+        }
+        // Upper bound: no shared prefix, a new restart point and three
+        // five-byte varints
+        let added = key.len() + value.len() + 4 + 15;
+        size + added > target && size * 100 > target * (100 - self.options.size_deviation)
+    }
+
+    /// Append an entry
+    ///
+    /// Panics unless `key` sorts after every key added before, in this block
+    /// or earlier ones.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) {
+        assert!(
+            !self.started || key > self.last_key.as_slice(),
+            "Keys must be added in increasing order"
+        );
+        // The first key of a block is the first restart point
+        let shared = if self.is_empty() {
+            0
+        } else if self.counter < self.options.restart_interval {
+            shared_prefix(&self.last_key, key)
+        } else {
+            self.restarts.push(self.buffer.len() as u32);
+            self.counter = 0;
+            0
+        };
+        put_varint(&mut self.buffer, shared as u32);
+        put_varint(&mut self.buffer, (key.len() - shared) as u32);
+        put_varint(&mut self.buffer, value.len() as u32);
+        self.buffer.extend_from_slice(&key[shared..]);
+        self.buffer.extend_from_slice(value);
+
+        self.last_key.truncate(shared);
+        self.last_key.extend_from_slice(&key[shared..]);
+        self.counter += 1;
+        self.entries += 1;
+        self.started = true;
+    }
+
+    /// The finished block, trailer included; the builder starts a new block
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut block = std::mem::take(&mut self.buffer);
+        for restart in &self.restarts {
+            block.extend_from_slice(&restart.to_le_bytes());
+        }
+        block.extend_from_slice(&(self.restarts.len() as u32).to_le_bytes());
+        block.push(NO_COMPRESSION);
+        let checksum = mask_crc(crc32c(&block));
+        block.extend_from_slice(&checksum.to_le_bytes());
+
+        self.buffer = Vec::with_capacity(self.options.block_size);
+        self.restarts = vec![0];
+        self.counter = 0;
+        self.entries = 0;
+        block
+    }
+}
+
+/// Split sorted entries into blocks of about `options.block_size` bytes
+///
+/// Panics if the keys are not strictly increasing.
+pub fn build_blocks<'a>(
+    entries: impl IntoIterator<Item = (&'a [u8], &'a [u8])>,
+    options: BlockOptions,
+) -> Vec<Vec<u8>> {
+    let mut builder = BlockBuilder::new(options);
+    let mut blocks = Vec::new();
+    for (key, value) in entries {
+        if builder.should_finish(key, value) {
+            blocks.push(builder.finish());
+        }
+        builder.add(key, value);
+    }
+    if !builder.is_empty() {
+        blocks.push(builder.finish());
+    }
+    blocks
+}
+
+/// A finished block, checked and ready for lookups
+///
+/// Entries are decoded on demand, so a block whose checksum is right but
+/// whose contents are malformed (a bug in whatever wrote it) is only noticed
+/// when the bad entry is reached; lookups and iteration then fail.
+#[derive(Debug, Clone, Copy)]
+pub struct Block<'a> {
+    /// The entries, without the restart array or trailer
+    data: &'a [u8],
+    restarts: &'a [u8],
+}
+
+/// Reconstructed key and the value it maps to
+pub type Entry<'a> = (Vec<u8>, &'a [u8]);
+
+impl<'a> Block<'a> {
+    /// Check the trailer and restart array of a block
+    pub fn new(block: &'a [u8]) -> Result<Self, String> {
+        if block.len() < 4 + TRAILER_SIZE {
+            return Err(format!("block of {} bytes is too short", block.len()));
+        }
+        let (contents, checksum) = block.split_at(block.len() - 4);
+        let expected = read_u32(checksum, 0);
+        let actual = mask_crc(crc32c(contents));
+        if actual != expected {
+            return Err(format!(
+                "block checksum mismatch: stored {:#010x}, computed {:#010x}",
+                expected, actual
+            ));
+        }
+        let (contents, kind) = contents.split_at(contents.len() - 1);
+        if kind[0] != NO_COMPRESSION {
+            return Err(format!("unsupported block type {}", kind[0]));
+        }
+
+        let count = read_u32(contents, contents.len() - 4) as usize;
+        let restarts_len = count
+            .checked_mul(4)
+            .filter(|&len| count > 0 && len <= contents.len() - 4)
+            .ok_or_else(|| format!("invalid restart count {}", count))?;
+        let data_len = contents.len() - 4 - restarts_len;
+        let block = Block {
+            data: &contents[..data_len],
+            restarts: &contents[data_len..contents.len() - 4],
+        };
+        let mut previous = None;
+        for i in 0..count {
+            let offset = block.restart(i);
+            if offset > data_len || previous.is_some_and(|p| offset <= p) {
+                return Err(format!("invalid restart offset {}", offset));
+            }
+            previous = Some(offset);
+        }
+        Ok(block)
+    }
+
+    /// Number of restart points
+    pub fn restarts(&self) -> usize {
+        self.restarts.len() / 4
+    }
+
+    /// Bytes taken by the entries
+    pub fn data_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Every entry in key order
+    pub fn iter(&self) -> BlockIter<'a> {
+        BlockIter {
+            data: self.data,
+            offset: 0,
+            key: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Entries from the first key at or after `target` onward
+    pub fn seek(&self, target: &[u8]) -> Result<BlockIter<'a>, String> {
+        // Last restart point whose key is below the target; the target, if
+        // present, is in its interval
+        let (mut low, mut high) = (0, self.restarts() - 1);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if self.restart_key(mid)? < target {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        let mut iter = BlockIter {
+            data: self.data,
+            offset: self.restart(low),
+            key: Vec::new(),
+            failed: false,
+        };
+        loop {
+            let start = iter.offset;
+            let Some(entry) = iter.next() else {
+                return Ok(iter);
+            };
+            let (key, _) = entry?;
+            if key.as_slice() >= target {
+                // Step back so this entry comes first. Decoding it only
+                // needs the prefix it shares with the previous key, which
+                // it begins with itself.
+                iter.offset = start;
+                iter.key = key;
+                return Ok(iter);
+            }
+        }
+    }
+
+    /// The value stored under `key`
+    pub fn get(&self, key: &[u8]) -> Result<Option<&'a [u8]>, String> {
+        match self.seek(key)?.next().transpose()? {
+            Some((found, value)) if found == key => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    fn restart(&self, index: usize) -> usize {
+        read_u32(self.restarts, 4 * index) as usize
+    }
+
+    /// The full key stored at restart point `index`
+    fn restart_key(&self, index: usize) -> Result<&'a [u8], String> {
+        let offset = self.restart(index);
+        let header = decode_header(self.data, offset)?;
+        if header.shared != 0 {
+            return Err(format!(
+                "entry at restart offset {} shares a prefix",
+                offset
+            ));
+        }
+        Ok(&self.data[header.key_start..header.key_start + header.unshared])
+    }
+}
+
+/// Iterates over a block's entries, rebuilding each key from its prefix
+pub struct BlockIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+    /// Key of the previous entry
+    key: Vec<u8>,
+    /// Set after a malformed entry, which ends the iteration
+    failed: bool,
+}
+
+impl<'a> Iterator for BlockIter<'a> {
+    type Item = Result<Entry<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.data.len() {
+            return None;
+        }
+        let header = decode_header(self.data, self.offset).and_then(|header| {
+            if header.shared > self.key.len() {
+                return Err(format!(
+                    "entry at offset {} shares more than the previous key",
+                    self.offset
+                ));
+            }
+            Ok(header)
+        });
+        let header = match header {
+            Ok(header) => header,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        let value_start = header.key_start + header.unshared;
+        self.key.truncate(header.shared);
+        self.key
+            .extend_from_slice(&self.data[header.key_start..value_start]);
+        self.offset = value_start + header.value_len;
+        Some(Ok((self.key.clone(), &self.data[value_start..self.offset])))
+    }
+}
+
+/// The lengths that start an entry, checked against the block
+struct Header {
+    shared: usize,
+    unshared: usize,
+    value_len: usize,
+    /// Offset of the unshared key bytes
+    key_start: usize,
+}
+
+fn decode_header(data: &[u8], offset: usize) -> Result<Header, String> {
+    let corrupt = || format!("malformed entry at offset {}", offset);
+    let mut position = offset;
+    let mut next = || {
+        let (value, len) = get_varint(&data[position..]).ok_or_else(corrupt)?;
+        position += len;
+        Ok::<_, String>(value as usize)
+    };
+    let (shared, unshared, value_len) = (next()?, next()?, next()?);
+    let end = position
+        .checked_add(unshared)
+        .and_then(|n| n.checked_add(value_len));
+    if end.is_none_or(|end| end > data.len()) {
+        return Err(corrupt());
+    }
+    Ok(Header {
+        shared,
+        unshared,
+        value_len,
+        key_start: position,
+    })
+}
+
+fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// LEB128: seven bits per byte, low bits first
+fn put_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// The value and its length in bytes, or `None` if truncated or too long
+fn get_varint(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as u32).checked_shl(7 * i as u32)?;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// CRC-32C (Castagnoli), the checksum RocksDB uses
+fn crc32c(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0x82f6_3b78
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// RocksDB's CRC masking, so a block that embeds CRCs does not checksum to
+/// something degenerate
+fn mask_crc(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(n: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..n)
+            .map(|i| {
+                let key = format!("user:{:06}", i * 2).into_bytes();
+                let value = format!("value-{}", i).into_bytes();
+                (key, value)
+            })
+            .collect()
+    }
+
+    fn build(entries: &[(Vec<u8>, Vec<u8>)], restart_interval: usize) -> Vec<u8> {
+        let mut builder = BlockBuilder::new(BlockOptions {
+            restart_interval,
+            ..BlockOptions::default()
+        });
+        for (key, value) in entries {
+            builder.add(key, value);
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn round_trip() {
+        let entries = entries(100);
+        for restart_interval in [1, 16, 1000] {
+            let bytes = build(&entries, restart_interval);
+            let block = Block::new(&bytes).unwrap();
+            assert_eq!(block.restarts(), 100usize.div_ceil(restart_interval));
+
+            let read: Vec<_> = block.iter().map(Result::unwrap).collect();
+            assert_eq!(read.len(), entries.len());
+            for ((key, value), (expected_key, expected_value)) in read.iter().zip(&entries) {
+                assert_eq!(key, expected_key);
+                assert_eq!(value, expected_value);
+            }
+            for (key, value) in &entries {
+                assert_eq!(block.get(key).unwrap(), Some(value.as_slice()));
+            }
+            // Odd keys fall between the stored ones
+            assert_eq!(block.get(b"user:000001").unwrap(), None);
+            assert_eq!(block.get(b"zzz").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn prefix_compression_shrinks_blocks() {
+        let entries = entries(100);
+        assert!(build(&entries, 16).len() < build(&entries, 1).len());
+    }
+
+    #[test]
+    fn current_size_matches_finished_block() {
+        let mut builder = BlockBuilder::new(BlockOptions::default());
+        for (key, value) in entries(40) {
+            builder.add(&key, &value);
+        }
+        let size = builder.current_size();
+        assert_eq!(builder.finish().len(), size);
+        assert!(builder.is_empty());
+    }
+
+    #[test]
+    fn seek() {
+        let entries = entries(50);
+        for restart_interval in [1, 4, 16, 100] {
+            let bytes = build(&entries, restart_interval);
+            let block = Block::new(&bytes).unwrap();
+            let first_key = |target: &[u8]| {
+                block
+                    .seek(target)
+                    .unwrap()
+                    .next()
+                    .map(|entry| entry.unwrap().0)
+            };
+
+            // Before the first key
+            assert_eq!(first_key(b"a"), Some(b"user:000000".to_vec()));
+            assert_eq!(first_key(b""), Some(b"user:000000".to_vec()));
+            // Exactly on a key, and between two keys
+            assert_eq!(first_key(b"user:000040"), Some(b"user:000040".to_vec()));
+            assert_eq!(first_key(b"user:000041"), Some(b"user:000042".to_vec()));
+            // After the last key
+            assert_eq!(first_key(b"user:000099"), None);
+            assert_eq!(first_key(b"zzz"), None);
+
+            // The iterator carries on in order from the sought key
+            let rest: Vec<_> = block
+                .seek(b"user:000041")
+                .unwrap()
+                .map(|entry| entry.unwrap().0)
+                .collect();
+            let expected: Vec<_> = entries[21..].iter().map(|(key, _)| key.clone()).collect();
+            assert_eq!(rest, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_flipped_byte() {
+        let bytes = build(&entries(20), 16);
+        for i in 0..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[i] ^= 0x01;
+            assert!(Block::new(&corrupt).is_err(), "flip at byte {}", i);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_block() {
+        let bytes = build(&entries(20), 16);
+        for len in 0..bytes.len() {
+            assert!(Block::new(&bytes[..len]).is_err(), "truncated to {}", len);
+        }
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    #[should_panic(expected = "increasing order")]
+    fn rejects_unsorted_keys() {
+        let mut builder = BlockBuilder::new(BlockOptions::default());
+        builder.add(b"b", b"");
+        builder.add(b"a", b"");
+    }
+
+    #[test]
+    fn finishes_within_size_deviation() {
+        let options = BlockOptions {
+            block_size: 1000,
+            restart_interval: 16,
+            size_deviation: 10,
+        };
+        let mut builder = BlockBuilder::new(options);
+        while builder.current_size() < 850 {
+            let key = format!("key{:06}", builder.len());
+            builder.add(key.as_bytes(), &[0; 20]);
+        }
+        // Under 90% full, a block takes an entry that overflows it
+        assert!(!builder.should_finish(b"zz", &[0; 200]));
+        while builder.current_size() <= 900 {
+            let key = format!("key{:06}", builder.len());
+            builder.add(key.as_bytes(), &[0; 20]);
+        }
+        // Past 90%, it is closed instead, unless the entry still fits
+        assert!(builder.should_finish(b"zz", &[0; 200]));
+        assert!(!builder.should_finish(b"zz", &[]));
+
+        let no_deviation = BlockBuilder {
+            options: BlockOptions {
+                size_deviation: 0,
+                ..options
+            },
+            ..builder
+        };
+        assert!(!no_deviation.should_finish(b"zz", &[0; 200]));
+
+        // Any entry that does not fit closes the block
+        let mut whole = BlockBuilder::new(BlockOptions {
+            size_deviation: 100,
+            ..options
+        });
+        whole.add(b"a", &[0; 20]);
+        assert!(whole.should_finish(b"zz", &[0; 1000]));
+        assert!(!whole.should_finish(b"zz", &[0; 20]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Size deviation must be a percentage")]
+    fn rejects_deviation_over_100() {
+        BlockBuilder::new(BlockOptions {
+            size_deviation: 101,
+            ..BlockOptions::default()
+        });
+    }
+
+    #[test]
+    fn build_blocks_splits_near_block_size() {
+        let options = BlockOptions {
+            block_size: 1000,
+            restart_interval: 16,
+            size_deviation: 10,
+        };
+        let entries = entries(500);
+        let blocks = build_blocks(
+            entries.iter().map(|(k, v)| (k.as_slice(), v.as_slice())),
+            options,
+        );
+        assert!(blocks.len() > 1);
+        for block in &blocks[..blocks.len() - 1] {
+            assert!(
+                block.len() > 900 && block.len() <= 1000,
+                "block of {} bytes",
+                block.len()
+            );
+        }
+
+        // Every entry lands in exactly one block, in order
+        let read: Vec<_> = blocks
+            .iter()
+            .flat_map(|bytes| {
+                let block = Block::new(bytes).unwrap();
+                block
+                    .iter()
+                    .map(Result::unwrap)
+                    .map(|(key, value)| (key, value.to_vec()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(read, entries);
+    }
+
+    #[test]
+    fn oversized_entry_gets_its_own_block() {
+        let options = BlockOptions {
+            block_size: 100,
+            ..BlockOptions::default()
+        };
+        let (small, big) = ([1u8; 80], [2u8; 500]);
+        let items: [(&[u8], &[u8]); 3] = [(b"a", &small), (b"b", &big), (b"c", &small)];
+        let blocks = build_blocks(items, options);
+        assert_eq!(blocks.len(), 3);
+        let middle = Block::new(&blocks[1]).unwrap();
+        assert_eq!(middle.get(b"b").unwrap(), Some(big.as_slice()));
+    }
+}
@@ -0,0 +1,343 @@
+//! Offset generators for read workloads.
+//!
+//! Reading the same offset every iteration lets the drive's own cache serve
+//! everything after the first read. These generators spread reads across a
+//! large file so each one is likely to reach the media.
+
+/// Small, fast, seedable PRNG (SplitMix64)
+///
+/// Benchmarks need reproducible offsets, not cryptographic randomness.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in [0, n)
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+/// How successive read offsets are chosen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OffsetPattern {
+    /// Every block in order, wrapping at the end of the file
+    Sequential,
+    /// Every block equally likely
+    Uniform,
+    /// A few hot blocks take most reads; `theta` in (0, 1) sets the skew
+    Zipfian { theta: f64 },
+}
+
+impl OffsetPattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OffsetPattern::Sequential => "sequential",
+            OffsetPattern::Uniform => "uniform",
+            OffsetPattern::Zipfian { .. } => "zipfian",
+        }
+    }
+}
+
+impl std::str::FromStr for OffsetPattern {
+    type Err = String;
+
+    /// `sequential`, `uniform`, `zipfian` (theta 0.99) or `zipfian:<theta>`
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            None if s == "sequential" => Ok(OffsetPattern::Sequential),
+            None if s == "uniform" => Ok(OffsetPattern::Uniform),
+            None if s == "zipfian" => Ok(OffsetPattern::Zipfian { theta: 0.99 }),
+            Some(("zipfian", theta)) => match theta.parse::<f64>() {
+                Ok(theta) if theta > 0.0 && theta < 1.0 => Ok(OffsetPattern::Zipfian { theta }),
+                _ => Err(format!("zipfian theta must be in (0, 1), got '{}'", theta)),
+            },
+            _ => Err(format!("unknown offset pattern '{}'", s)),
+        }
+    }
+}
+
+/// Zipfian rank generator from Gray et al., "Quickly Generating
+/// Billion-Record Synthetic Databases" (the one YCSB uses)
+#[derive(Debug, Clone)]
+struct Zipf {
+    n: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipf {
+    fn new(n: u64, theta: f64) -> Self {
+        assert!(
+            theta > 0.0 && theta < 1.0,
+            "zipfian theta must be in (0, 1)"
+        );
+        let zetan = zeta(n, theta);
+        let zeta2 = zeta(2.min(n), theta);
+        Zipf {
+            n,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan),
+        }
+    }
+
+    /// Rank in [0, n), where rank 0 is the most popular
+    fn sample(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.n - 1);
+        }
+        let rank = (self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64;
+        rank.min(self.n - 1)
+    }
+}
+
+/// Terms summed exactly before `zeta` switches to its approximation
+const ZETA_EXACT_TERMS: u64 = 1000;
+
+/// The generalized harmonic number: the sum of `1 / i^theta` for i in 1..=n
+///
+/// Summing a term per block costs seconds for a multi-GB file of 4KB blocks,
+/// and every `OffsetGen` needs it. Past the first `ZETA_EXACT_TERMS` terms
+/// the tail is replaced by its Euler-Maclaurin expansion, which agrees with
+/// the exact sum to better than one part in 10^12.
+fn zeta(n: u64, theta: f64) -> f64 {
+    let m = n.min(ZETA_EXACT_TERMS);
+    let head: f64 = (1..=m).map(|i| (i as f64).powf(-theta)).sum();
+    if n == m {
+        return head;
+    }
+    // Sum over (m, n] of x^-theta: the integral, the endpoint correction and
+    // the first derivative term
+    let (m, n) = (m as f64, n as f64);
+    let integral = (n.powf(1.0 - theta) - m.powf(1.0 - theta)) / (1.0 - theta);
+    let ends = (n.powf(-theta) - m.powf(-theta)) / 2.0;
+    let slope = theta / 12.0 * (m.powf(-theta - 1.0) - n.powf(-theta - 1.0));
+    head + integral + ends + slope
+}
+
+/// Scatter Zipfian ranks across the file so hot blocks are not all adjacent
+fn scramble(rank: u64, n: u64) -> u64 {
+    // FNV-1a over the rank's bytes
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in rank.to_le_bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash % n
+}
+
+/// Endless stream of block-aligned offsets within a file
+#[derive(Debug, Clone)]
+pub struct OffsetGen {
+    pattern: OffsetPattern,
+    block_size: u64,
+    blocks: u64,
+    next_block: u64,
+    rng: Rng,
+    zipf: Option<Zipf>,
+}
+
+impl OffsetGen {
+    /// Generate offsets of `block_size`-aligned blocks in a file of `file_size` bytes
+    ///
+    /// Offsets always leave room for a full block before the end of the file.
+    pub fn new(pattern: OffsetPattern, file_size: u64, block_size: u64, seed: u64) -> Self {
+        let blocks = (file_size / block_size).max(1);
+        let zipf = match pattern {
+            OffsetPattern::Zipfian { theta } => Some(Zipf::new(blocks, theta)),
+            _ => None,
+        };
+        OffsetGen {
+            pattern,
+            block_size,
+            blocks,
+            next_block: 0,
+            rng: Rng::new(seed),
+            zipf,
+        }
+    }
+
+    pub fn pattern(&self) -> OffsetPattern {
+        self.pattern
+    }
+
+    /// Number of distinct blocks offsets are drawn from
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    pub fn next_offset(&mut self) -> u64 {
+        let block = match self.pattern {
+            OffsetPattern::Sequential => {
+                let block = self.next_block;
+                self.next_block = (self.next_block + 1) % self.blocks;
+                block
+            }
+            OffsetPattern::Uniform => self.rng.below(self.blocks),
+            OffsetPattern::Zipfian { .. } => {
+                let rank = self.zipf.as_ref().unwrap().sample(&mut self.rng);
+                scramble(rank, self.blocks)
+            }
+        };
+        block * self.block_size
+    }
+}
+
+impl Iterator for OffsetGen {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        Some(self.next_offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const PATTERNS: [OffsetPattern; 3] = [
+        OffsetPattern::Sequential,
+        OffsetPattern::Uniform,
+        OffsetPattern::Zipfian { theta: 0.99 },
+    ];
+
+    #[test]
+    fn offsets_in_range_and_aligned() {
+        for pattern in PATTERNS {
+            for (file_size, block_size) in [(1 << 20, 4096), (10_000, 512), (100, 4096)] {
+                let mut offsets = OffsetGen::new(pattern, file_size, block_size, 1);
+                let blocks = offsets.blocks();
+                assert_eq!(blocks, (file_size / block_size).max(1));
+                for offset in offsets.by_ref().take(10_000) {
+                    assert_eq!(offset % block_size, 0);
+                    assert!(offset / block_size < blocks, "{:?} {}", pattern, offset);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sequential_wraps() {
+        let offsets: Vec<_> = OffsetGen::new(OffsetPattern::Sequential, 3 * 512, 512, 0)
+            .take(7)
+            .collect();
+        assert_eq!(offsets, [0, 512, 1024, 0, 512, 1024, 0]);
+    }
+
+    #[test]
+    fn same_seed_same_offsets() {
+        for pattern in PATTERNS {
+            let run = |seed| -> Vec<u64> {
+                OffsetGen::new(pattern, 1 << 30, 4096, seed)
+                    .take(1000)
+                    .collect()
+            };
+            assert_eq!(run(42), run(42));
+            if pattern != OffsetPattern::Sequential {
+                assert_ne!(run(42), run(43));
+            }
+        }
+    }
+
+    /// Share of samples that land on the hottest 1% of blocks
+    fn hot_share(pattern: OffsetPattern) -> f64 {
+        let blocks = 10_000;
+        let samples = 200_000;
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for offset in OffsetGen::new(pattern, blocks * 4096, 4096, 7).take(samples) {
+            *counts.entry(offset).or_default() += 1;
+        }
+        let mut counts: Vec<usize> = counts.into_values().collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let hot: usize = counts.iter().take(blocks as usize / 100).sum();
+        hot as f64 / samples as f64
+    }
+
+    #[test]
+    fn zipfian_is_skewed() {
+        // For theta 0.99 over 10,000 blocks the top 100 ranks take about
+        // zeta(100) / zeta(10000), a little over half of the reads
+        let zipfian = hot_share(OffsetPattern::Zipfian { theta: 0.99 });
+        assert!(zipfian > 0.45 && zipfian < 0.65, "{}", zipfian);
+        let uniform = hot_share(OffsetPattern::Uniform);
+        assert!(uniform < 0.05, "{}", uniform);
+    }
+
+    #[test]
+    fn zipf_tiny_ranges() {
+        let mut rng = Rng::new(3);
+        for n in [1, 2, 3] {
+            let zipf = Zipf::new(n, 0.5);
+            for _ in 0..1000 {
+                assert!(zipf.sample(&mut rng) < n);
+            }
+        }
+    }
+
+    #[test]
+    fn zeta_approximation_matches_sum() {
+        for theta in [0.2, 0.5, 0.99] {
+            for n in [1, 999, 1000, 1001, 5000, 2_000_000] {
+                let exact: f64 = (1..=n).map(|i| (i as f64).powf(-theta)).sum();
+                let approx = zeta(n, theta);
+                assert!(
+                    ((approx - exact) / exact).abs() < 1e-12,
+                    "theta {} n {}: {} vs {}",
+                    theta,
+                    n,
+                    approx,
+                    exact
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scramble_spreads_ranks() {
+        let n = 10_000;
+        let blocks: std::collections::HashSet<u64> = (0..n).map(|rank| scramble(rank, n)).collect();
+        assert!(blocks.iter().all(|&block| block < n));
+        // A random function hits about 1 - 1/e of the blocks
+        assert!(blocks.len() > n as usize / 2);
+        // The hottest ranks are not neighbours
+        assert!(scramble(0, n).abs_diff(scramble(1, n)) > 1);
+    }
+
+    #[test]
+    fn parse_patterns() {
+        assert_eq!("uniform".parse(), Ok(OffsetPattern::Uniform));
+        assert_eq!(
+            "zipfian:0.5".parse(),
+            Ok(OffsetPattern::Zipfian { theta: 0.5 })
+        );
+        for bad in ["zipfian:1", "zipfian:0", "zipfian:x", "random", "uniform:1"] {
+            assert!(bad.parse::<OffsetPattern>().is_err(), "{}", bad);
+        }
+    }
+}